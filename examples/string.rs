@@ -1,18 +1,47 @@
 use std::{io::Write, time::SystemTime};
 
-fn build_string_message(data: &str) -> anyhow::Result<Vec<u8>> {
-    let mut msg = vec![0; std::mem::size_of::<u32>() + data.len()];
-    // ROS 1 message strings are encoded as 4-bytes length and then the byte data.
-    let mut w = std::io::Cursor::new(&mut msg);
-    w.write_all(&(data.len() as u32).to_le_bytes())?;
-    w.write_all(data.as_bytes())?;
-    Ok(msg)
+use foxglove_ws::{MessageEncoding, SchemaDescriptor};
+use futures_util::StreamExt;
+
+/// A ROS 1 `std_msgs/String`.
+struct StringMessage(String);
+
+impl MessageEncoding for StringMessage {
+    type Message = Self;
+
+    fn encoding() -> String {
+        "ros1".to_owned()
+    }
+
+    fn schema_name() -> String {
+        "std_msgs/String".to_owned()
+    }
+
+    fn schema() -> anyhow::Result<SchemaDescriptor> {
+        Ok("string data".into())
+    }
+
+    fn schema_encoding() -> Option<String> {
+        Some("ros1msg".to_owned())
+    }
+
+    fn encode(message: &Self) -> anyhow::Result<Vec<u8>> {
+        let mut msg = vec![0; std::mem::size_of::<u32>() + message.0.len()];
+        // ROS 1 message strings are encoded as 4-bytes length and then the byte data.
+        let mut w = std::io::Cursor::new(&mut msg);
+        w.write_all(&(message.0.len() as u32).to_le_bytes())?;
+        w.write_all(message.0.as_bytes())?;
+        Ok(msg)
+    }
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     env_logger::init();
-    let server = foxglove_ws::FoxgloveWebSocket::default();
+    let server = foxglove_ws::FoxgloveWebSocket::builder()
+        .name("string example")
+        .metadata("version", env!("CARGO_PKG_VERSION"))
+        .build();
 
     let urdf_robot = urdf_rs::Robot {
         name: "base".to_string(),
@@ -65,39 +94,37 @@ async fn main() -> anyhow::Result<()> {
 
     server
         .parameters
-        .write()
-        .await
-        .insert("/robot_description ".to_string(), urdf_string);
+        .set("/robot_description ", urdf_string)
+        .await?;
 
     tokio::spawn({
         let server = server.clone();
         async move { server.serve(([127, 0, 0, 1], 8765)).await }
     });
+    tokio::spawn({
+        let mut client_messages = server.subscribe_client_messages("/client_data").await;
+        async move {
+            while let Some(message) = client_messages.next().await {
+                log::info!(
+                    "Client {} published {} bytes ({}).",
+                    message.client_id,
+                    message.data.len(),
+                    message.channel.encoding
+                );
+            }
+        }
+    });
     let channel = server
-        .create_publisher(
-            "/data",
-            "ros1",
-            "std_msgs/String",
-            "string data",
-            Some("ros1msg"),
-            false,
-        )
+        .create_typed_publisher::<StringMessage>("/data", false)
         .await?;
     let channel_latching = server
-        .create_publisher(
-            "/data_latching",
-            "ros1",
-            "std_msgs/String",
-            "string data",
-            Some("ros1msg"),
-            true,
-        )
+        .create_typed_publisher::<StringMessage>("/data_latching", true)
         .await?;
 
     channel_latching
         .send(
             SystemTime::now().elapsed().unwrap().as_nanos() as u64,
-            &build_string_message("latching!")?,
+            &StringMessage("latching!".to_owned()),
         )
         .await?;
 
@@ -106,7 +133,7 @@ async fn main() -> anyhow::Result<()> {
         channel
             .send(
                 SystemTime::now().elapsed().unwrap().as_nanos() as u64,
-                &build_string_message(&format!("Hello {}!", counter))?,
+                &StringMessage(format!("Hello {}!", counter)),
             )
             .await?;
         counter += 1;
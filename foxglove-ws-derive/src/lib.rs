@@ -0,0 +1,208 @@
+//! Derive macros for [foxglove-ws](https://crates.io/crates/foxglove-ws). Use them through the
+//! `derive` feature of foxglove-ws, which re-exports them.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{ext::IdentExt, parse_macro_input, Data, DeriveInput, Fields, LitStr};
+
+/// Derives `foxglove_ws::ros1::Ros1Message` for a struct with named fields, enabled with the
+/// `derive` feature of foxglove-ws. The struct implements `foxglove_ws::MessageEncoding` as well
+/// and is encoded like with `foxglove_ws::ros1::Ros1`.
+///
+/// The fields are serialized in the order they are declared and have to implement
+/// `foxglove_ws::ros1::Ros1Field`. The name of the message type is set with
+/// `#[ros1(name = "package/Type")]`, it defaults to the name of the crate and the name of the
+/// struct.
+///
+/// # Example
+///
+/// ```
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// use foxglove_ws::ros1::{Ros1Message, Time};
+///
+/// #[derive(Ros1Message)]
+/// #[ros1(name = "geometry_msgs/Point")]
+/// struct Point {
+///     x: f64,
+///     y: f64,
+///     z: f64,
+/// }
+///
+/// #[derive(Ros1Message)]
+/// #[ros1(name = "nav_msgs/Path")]
+/// struct Path {
+///     stamp: Time,
+///     frame_id: String,
+///     points: Vec<Point>,
+/// }
+///
+/// let server = foxglove_ws::FoxgloveWebSocket::new("robot");
+/// let channel = server.create_typed_publisher::<Path>("/path", false).await?;
+/// let path = Path {
+///     stamp: Time { sec: 1, nsec: 0 },
+///     frame_id: "map".to_owned(),
+///     points: vec![Point { x: 1.0, y: 2.0, z: 0.0 }],
+/// };
+/// channel.send(1_000_000_000, &path).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[proc_macro_derive(Ros1Message, attributes(ros1))]
+pub fn derive_ros1_message(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_ros1_message(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_ros1_message(input: DeriveInput) -> syn::Result<TokenStream2> {
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "Ros1Message cannot be derived for generic types",
+        ));
+    }
+    let fields: Vec<_> = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields.named.iter().collect(),
+            Fields::Unit => vec![],
+            Fields::Unnamed(_) => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "Ros1Message cannot be derived for tuple structs",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "Ros1Message can only be derived for structs",
+            ))
+        }
+    };
+
+    let ident = &input.ident;
+    let name = match message_name(&input)? {
+        Some(name) => quote!(#name),
+        None => {
+            let type_name = ident.unraw().to_string();
+            quote!(::std::concat!(::std::env!("CARGO_CRATE_NAME"), "/", #type_name))
+        }
+    };
+    let field_idents: Vec<_> = fields.iter().map(|field| &field.ident).collect();
+    let field_names: Vec<_> = field_idents
+        .iter()
+        .map(|ident| ident.as_ref().map(|ident| ident.unraw().to_string()))
+        .collect();
+    let field_types: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+    let ros1_encoding = quote!(<::foxglove_ws::ros1::Ros1<Self> as ::foxglove_ws::MessageEncoding>);
+
+    Ok(quote! {
+        impl ::foxglove_ws::ros1::Ros1Field for #ident {
+            fn field_type() -> ::foxglove_ws::ros1::FieldType {
+                ::foxglove_ws::ros1::FieldType::Message(::std::string::ToString::to_string(#name))
+            }
+
+            fn add_definitions(definitions: &mut ::foxglove_ws::ros1::MessageDefinitions) {
+                if definitions.get(#name).is_none() {
+                    definitions.insert(
+                        <Self as ::foxglove_ws::ros1::Ros1Message>::definition(),
+                    );
+                    #(
+                        <#field_types as ::foxglove_ws::ros1::Ros1Field>::add_definitions(
+                            definitions,
+                        );
+                    )*
+                }
+            }
+
+            fn write(
+                &self,
+                buffer: &mut ::std::vec::Vec<u8>,
+            ) -> ::foxglove_ws::ros1::__private::Result<()> {
+                #(
+                    ::foxglove_ws::ros1::Ros1Field::write(&self.#field_idents, buffer)?;
+                )*
+                ::std::result::Result::Ok(())
+            }
+        }
+
+        impl ::foxglove_ws::ros1::Ros1Message for #ident {
+            fn definition() -> ::foxglove_ws::ros1::MessageDefinition {
+                ::foxglove_ws::ros1::MessageDefinition::new(
+                    #name,
+                    ::std::vec![
+                        #(
+                            ::foxglove_ws::ros1::Field {
+                                name: ::std::string::ToString::to_string(#field_names),
+                                field_type:
+                                    <#field_types as ::foxglove_ws::ros1::Ros1Field>::field_type(),
+                            },
+                        )*
+                    ],
+                )
+            }
+        }
+
+        impl ::foxglove_ws::MessageEncoding for #ident {
+            type Message = Self;
+
+            fn encoding() -> ::std::string::String {
+                #ros1_encoding::encoding()
+            }
+
+            fn schema_name() -> ::std::string::String {
+                #ros1_encoding::schema_name()
+            }
+
+            fn schema() -> ::foxglove_ws::ros1::__private::Result<::foxglove_ws::SchemaDescriptor> {
+                #ros1_encoding::schema()
+            }
+
+            fn schema_encoding() -> ::std::option::Option<::std::string::String> {
+                #ros1_encoding::schema_encoding()
+            }
+
+            fn encode(
+                message: &Self,
+            ) -> ::foxglove_ws::ros1::__private::Result<::std::vec::Vec<u8>> {
+                #ros1_encoding::encode(message)
+            }
+        }
+    })
+}
+
+/// Reads the name of the message type from `#[ros1(name = "package/Type")]`.
+fn message_name(input: &DeriveInput) -> syn::Result<Option<LitStr>> {
+    let mut name = None;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("ros1"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                let value: LitStr = meta.value()?.parse()?;
+                let is_valid = value
+                    .value()
+                    .split_once('/')
+                    .is_some_and(|(package, type_name)| {
+                        !package.is_empty() && !type_name.is_empty() && !type_name.contains('/')
+                    });
+                if !is_valid {
+                    return Err(syn::Error::new_spanned(
+                        &value,
+                        "expected a message type name like \"package/Type\"",
+                    ));
+                }
+                name = Some(value);
+                Ok(())
+            } else {
+                Err(meta.error("unsupported ros1 attribute"))
+            }
+        })?;
+    }
+    Ok(name)
+}
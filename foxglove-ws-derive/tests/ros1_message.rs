@@ -0,0 +1,248 @@
+//! Definitions, schemas and wire bytes of derived ROS 1 message types.
+
+use foxglove_ws::{
+    ros1::{
+        Duration, Field, FieldType, MessageDefinition, MessageDefinitions, Ros1, Ros1Field,
+        Ros1Message, Time,
+    },
+    FoxgloveWebSocket, MessageEncoding,
+};
+use serde_json::json;
+
+const SEPARATOR: &str =
+    "================================================================================";
+
+#[derive(Ros1Message)]
+#[ros1(name = "geometry_msgs/Point")]
+struct Point {
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+#[derive(Ros1Message)]
+#[ros1(name = "std_msgs/Header")]
+struct Header {
+    seq: u32,
+    stamp: Time,
+    frame_id: String,
+}
+
+#[derive(Ros1Message)]
+#[ros1(name = "demo_msgs/Track")]
+struct Track {
+    header: Header,
+    r#type: u8,
+    age: Duration,
+    corners: [Point; 2],
+    path: Vec<Point>,
+    labels: Vec<String>,
+    flags: [bool; 3],
+    scores: Vec<f32>,
+}
+
+#[derive(Ros1Message)]
+struct Empty {}
+
+fn track() -> Track {
+    Track {
+        header: Header {
+            seq: 258,
+            stamp: Time { sec: 1, nsec: 2 },
+            frame_id: "map".to_owned(),
+        },
+        r#type: 7,
+        age: Duration { sec: -1, nsec: 5 },
+        corners: [
+            Point {
+                x: 1.0,
+                y: 2.0,
+                z: 3.0,
+            },
+            Point {
+                x: -1.0,
+                y: -2.0,
+                z: -3.0,
+            },
+        ],
+        path: vec![Point {
+            x: 0.5,
+            y: 0.0,
+            z: 0.0,
+        }],
+        labels: vec!["a".to_owned(), "bc".to_owned()],
+        flags: [true, false, true],
+        scores: vec![],
+    }
+}
+
+#[test]
+fn derives_definitions() {
+    assert_eq!(
+        Track::definition(),
+        MessageDefinition::new(
+            "demo_msgs/Track",
+            vec![
+                field("header", FieldType::Message("std_msgs/Header".to_owned())),
+                field("type", FieldType::UInt8),
+                field("age", FieldType::Duration),
+                field("corners", array("geometry_msgs/Point", Some(2))),
+                field("path", array("geometry_msgs/Point", None)),
+                field(
+                    "labels",
+                    FieldType::Array(Box::new(FieldType::String), None)
+                ),
+                field(
+                    "flags",
+                    FieldType::Array(Box::new(FieldType::Bool), Some(3))
+                ),
+                field(
+                    "scores",
+                    FieldType::Array(Box::new(FieldType::Float32), None)
+                ),
+            ],
+        )
+    );
+    assert_eq!(Track::field_type().to_string(), "demo_msgs/Track");
+    // Without a name, the message type is named after the crate and the struct.
+    assert_eq!(Empty::definition().name, "ros1_message/Empty");
+    assert!(Empty::definition().fields.is_empty());
+}
+
+#[test]
+fn derives_schema() {
+    let expected = format!(
+        "std_msgs/Header header\n\
+         uint8 type\n\
+         duration age\n\
+         geometry_msgs/Point[2] corners\n\
+         geometry_msgs/Point[] path\n\
+         string[] labels\n\
+         bool[3] flags\n\
+         float32[] scores\n\
+         {0}\n\
+         MSG: std_msgs/Header\n\
+         uint32 seq\n\
+         time stamp\n\
+         string frame_id\n\
+         {0}\n\
+         MSG: geometry_msgs/Point\n\
+         float64 x\n\
+         float64 y\n\
+         float64 z",
+        SEPARATOR
+    );
+    assert_eq!(Track::schema_name(), "demo_msgs/Track");
+    assert_eq!(Track::encoding(), "ros1");
+    assert_eq!(Track::schema_encoding().as_deref(), Some("ros1msg"));
+    assert!(Track::schema().is_ok());
+    let mut definitions = MessageDefinitions::new();
+    Track::add_definitions(&mut definitions);
+    assert_eq!(definitions.schema("demo_msgs/Track").unwrap(), expected);
+}
+
+#[test]
+fn serializes_to_wire_format() {
+    let mut expected = vec![];
+    // header
+    expected.extend([0x02, 0x01, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0]);
+    expected.extend(b"map");
+    // type and age
+    expected.extend([7, 0xff, 0xff, 0xff, 0xff, 5, 0, 0, 0]);
+    // corners, without a length
+    for value in [1.0_f64, 2.0, 3.0, -1.0, -2.0, -3.0] {
+        expected.extend(value.to_le_bytes());
+    }
+    // path
+    expected.extend([1, 0, 0, 0]);
+    for value in [0.5_f64, 0.0, 0.0] {
+        expected.extend(value.to_le_bytes());
+    }
+    // labels
+    expected.extend([2, 0, 0, 0, 1, 0, 0, 0, b'a', 2, 0, 0, 0, b'b', b'c']);
+    // flags and scores
+    expected.extend([1, 0, 1, 0, 0, 0, 0]);
+
+    assert_eq!(Track::encode(&track()).unwrap(), expected);
+}
+
+#[test]
+fn serializes_like_message_definitions() {
+    let mut definitions = MessageDefinitions::new();
+    Track::add_definitions(&mut definitions);
+    let message = json!({
+        "header": { "seq": 258, "stamp": { "sec": 1, "nsec": 2 }, "frame_id": "map" },
+        "type": 7,
+        "age": { "sec": -1, "nsec": 5 },
+        "corners": [{ "x": 1.0, "y": 2.0, "z": 3.0 }, { "x": -1.0, "y": -2.0, "z": -3.0 }],
+        "path": [{ "x": 0.5, "y": 0.0, "z": 0.0 }],
+        "labels": ["a", "bc"],
+        "flags": [true, false, true],
+        "scores": [],
+    });
+    assert_eq!(
+        definitions.serialize("demo_msgs/Track", &message).unwrap(),
+        Track::encode(&track()).unwrap()
+    );
+}
+
+/// A message type implemented by hand that forgets to add the definition of its nested type.
+struct Incomplete {
+    point: Point,
+}
+
+impl Ros1Field for Incomplete {
+    fn field_type() -> FieldType {
+        FieldType::Message("demo_msgs/Incomplete".to_owned())
+    }
+
+    fn add_definitions(definitions: &mut MessageDefinitions) {
+        definitions.insert(Self::definition());
+    }
+
+    fn write(&self, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+        self.point.write(buffer)
+    }
+}
+
+impl Ros1Message for Incomplete {
+    fn definition() -> MessageDefinition {
+        MessageDefinition::new(
+            "demo_msgs/Incomplete",
+            vec![field("point", Point::field_type())],
+        )
+    }
+}
+
+#[tokio::test]
+async fn fails_to_publish_incomplete_definitions() {
+    let Err(err) = Ros1::<Incomplete>::schema() else {
+        panic!("Built a schema without the definition of geometry_msgs/Point.");
+    };
+    assert_eq!(
+        err.to_string(),
+        "Unknown message type geometry_msgs/Point. Used by demo_msgs/Incomplete."
+    );
+
+    let server = FoxgloveWebSocket::new("robot");
+    assert!(server
+        .create_typed_publisher::<Ros1<Incomplete>>("/incomplete", false)
+        .await
+        .is_err());
+    let channel = server
+        .create_typed_publisher::<Track>("/track", false)
+        .await
+        .unwrap();
+    channel.send(0, &track()).await.unwrap();
+}
+
+fn field(name: &str, field_type: FieldType) -> Field {
+    Field {
+        name: name.to_owned(),
+        field_type,
+    }
+}
+
+fn array(element: &str, length: Option<usize>) -> FieldType {
+    FieldType::Array(Box::new(FieldType::Message(element.to_owned())), length)
+}
@@ -0,0 +1,514 @@
+//! Assets that clients can fetch, e.g. meshes referenced by URDFs (`assets` capability).
+
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{Cursor, Write},
+    mem::size_of,
+    path::{Component, Path, PathBuf},
+    pin::pin,
+    sync::Arc,
+};
+
+use anyhow::anyhow;
+use futures_util::future::{self, BoxFuture, Either};
+use tokio::sync::{RwLock, Semaphore};
+use warp::ws::Message;
+
+use crate::queue::ClientQueue;
+
+/// Number of assets a client may fetch at once. Further requests are rejected until a fetch
+/// finished.
+pub(crate) const MAX_CONCURRENT_FETCHES: usize = 4;
+
+/// Source of the assets that clients fetch by URI.
+pub trait AssetProvider: Send + Sync + 'static {
+    /// Returns the content of the asset at `uri`.
+    fn fetch<'a>(&'a self, uri: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<u8>>>;
+}
+
+/// Serves files below a root directory.
+///
+/// URIs of the form `package://<package>/<path>` resolve to `<path>` inside the directory
+/// registered for `<package>`, or inside `<root>/<package>` if no directory was registered.
+/// `file://` URIs and plain relative paths resolve against the root. Files outside of the root
+/// or package directories are never served, neither through `..` nor through symlinks.
+#[derive(Clone, Debug)]
+pub struct FileSystemAssetProvider {
+    root: PathBuf,
+    packages: HashMap<String, PathBuf>,
+}
+
+impl FileSystemAssetProvider {
+    /// Creates a provider serving the files below `root`.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            packages: HashMap::new(),
+        }
+    }
+
+    /// Serves `package://<name>/...` URIs from `path` instead of `<root>/<name>`.
+    pub fn with_package(mut self, name: &str, path: impl Into<PathBuf>) -> Self {
+        self.packages.insert(name.to_owned(), path.into());
+        self
+    }
+
+    /// Splits `uri` into the directory it is confined to and the path within that directory.
+    fn resolve<'a>(&self, uri: &'a str) -> anyhow::Result<(PathBuf, &'a str)> {
+        if let Some(package_path) = uri.strip_prefix("package://") {
+            let (package, path) = package_path
+                .split_once('/')
+                .ok_or(anyhow!("Missing path in {}.", uri))?;
+            if package.is_empty() {
+                return Err(anyhow!("Missing package in {}.", uri));
+            }
+            match self.packages.get(package) {
+                Some(directory) => Ok((directory.clone(), path)),
+                // The package directory itself may be a symlink, so the asset is confined to the
+                // root rather than to the package directory.
+                None => Ok((self.root.clone(), package_path)),
+            }
+        } else if let Some(path) = uri.strip_prefix("file://") {
+            let path = Path::new(path);
+            let path = match path.strip_prefix(&self.root) {
+                Ok(relative) => relative,
+                Err(_) if path.is_relative() => path,
+                Err(_) => return Err(anyhow!("{} is outside of the asset root.", uri)),
+            };
+            Ok((
+                self.root.clone(),
+                path.to_str().ok_or(anyhow!("Invalid path in {}.", uri))?,
+            ))
+        } else if uri.contains("://") {
+            Err(anyhow!("Unsupported URI {}.", uri))
+        } else {
+            Ok((self.root.clone(), uri))
+        }
+    }
+}
+
+/// Joins `path` to `directory`, rejecting absolute paths and paths that climb up with `..`.
+fn join_relative(directory: &Path, path: &str) -> anyhow::Result<PathBuf> {
+    let mut joined = directory.to_path_buf();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(name) => joined.push(name),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(anyhow!("Path {} leaves its directory.", path));
+            }
+        }
+    }
+    Ok(joined)
+}
+
+impl AssetProvider for FileSystemAssetProvider {
+    fn fetch<'a>(&'a self, uri: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<u8>>> {
+        Box::pin(async move {
+            let (directory, path) = self.resolve(uri)?;
+            let path = join_relative(&directory, path)?;
+
+            // Resolve symlinks before checking that the file is still inside its directory.
+            let directory = tokio::fs::canonicalize(&directory).await?;
+            let path = tokio::fs::canonicalize(&path)
+                .await
+                .map_err(|err| anyhow!("Asset {} not found: {}.", uri, err))?;
+            if !path.starts_with(&directory) {
+                return Err(anyhow!("Asset {} is outside of its directory.", uri));
+            }
+            Ok(tokio::fs::read(&path).await?)
+        })
+    }
+}
+
+/// Serves assets from memory. Assets can be added and removed while the server is running.
+#[derive(Clone, Debug, Default)]
+pub struct InMemoryAssetProvider {
+    assets: Arc<RwLock<HashMap<String, Arc<Vec<u8>>>>>,
+}
+
+impl InMemoryAssetProvider {
+    /// Creates an empty provider.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds or replaces the asset at `uri`.
+    pub async fn insert(&self, uri: &str, data: impl Into<Vec<u8>>) {
+        self.assets
+            .write()
+            .await
+            .insert(uri.to_owned(), Arc::new(data.into()));
+    }
+
+    /// Removes the asset at `uri`.
+    pub async fn remove(&self, uri: &str) {
+        self.assets.write().await.remove(uri);
+    }
+}
+
+impl AssetProvider for InMemoryAssetProvider {
+    fn fetch<'a>(&'a self, uri: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<u8>>> {
+        Box::pin(async move {
+            let data = self
+                .assets
+                .read()
+                .await
+                .get(uri)
+                .cloned()
+                .ok_or(anyhow!("Asset {} not found.", uri))?;
+            Ok(data.to_vec())
+        })
+    }
+}
+
+/// Serves assets compiled into the binary, e.g. with `include_bytes!`.
+#[derive(Clone, Debug, Default)]
+pub struct EmbeddedAssetProvider {
+    assets: HashMap<String, &'static [u8]>,
+}
+
+impl EmbeddedAssetProvider {
+    /// Creates an empty provider.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the asset at `uri`.
+    pub fn with_asset(mut self, uri: &str, data: &'static [u8]) -> Self {
+        self.assets.insert(uri.to_owned(), data);
+        self
+    }
+}
+
+impl<S: Into<String>> FromIterator<(S, &'static [u8])> for EmbeddedAssetProvider {
+    fn from_iter<I: IntoIterator<Item = (S, &'static [u8])>>(assets: I) -> Self {
+        Self {
+            assets: assets
+                .into_iter()
+                .map(|(uri, data)| (uri.into(), data))
+                .collect(),
+        }
+    }
+}
+
+impl AssetProvider for EmbeddedAssetProvider {
+    fn fetch<'a>(&'a self, uri: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<u8>>> {
+        Box::pin(async move {
+            self.assets
+                .get(uri)
+                .map(|data| data.to_vec())
+                .ok_or(anyhow!("Asset {} not found.", uri))
+        })
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct AssetState {
+    pub(crate) provider: RwLock<Option<Arc<dyn AssetProvider>>>,
+}
+
+impl fmt::Debug for AssetState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AssetState").finish_non_exhaustive()
+    }
+}
+
+fn build_fetch_asset_response(
+    request_id: u32,
+    result: &anyhow::Result<Vec<u8>>,
+) -> anyhow::Result<Message> {
+    let error = match result {
+        Ok(_) => String::new(),
+        Err(err) => err.to_string(),
+    };
+    let data: &[u8] = match result {
+        Ok(data) => data,
+        Err(_) => &[],
+    };
+    let mut buffer = vec![0; 2 * size_of::<u8>() + 2 * size_of::<u32>() + error.len() + data.len()];
+    {
+        let mut w = Cursor::new(&mut buffer);
+        // Write op code for the "Fetch Asset Response" type.
+        w.write_all(&4_u8.to_le_bytes())?;
+        w.write_all(&request_id.to_le_bytes())?;
+        // Status is 0 for success and 1 for an error.
+        w.write_all(&u8::from(result.is_err()).to_le_bytes())?;
+        w.write_all(&(error.len() as u32).to_le_bytes())?;
+        w.write_all(error.as_bytes())?;
+        w.write_all(data)?;
+    }
+    Ok(Message::binary(buffer))
+}
+
+/// Replies to an asset request with an error without fetching the asset.
+pub(crate) fn reject_fetch_asset(
+    tx: &ClientQueue,
+    request_id: u32,
+    message: &str,
+) -> anyhow::Result<()> {
+    tx.reply(build_fetch_asset_response(
+        request_id,
+        &Err(anyhow!("{}", message)),
+    )?)
+}
+
+/// Fetches an asset in the background and replies to the requesting client with either the
+/// content or an error.
+///
+/// Each fetch holds a permit of `fetches`, the client's fetches in flight. The request is
+/// rejected if no permit is left. The fetch is abandoned once the client's queue closes.
+pub(crate) fn fetch_asset(
+    tx: &Arc<ClientQueue>,
+    fetches: &Arc<Semaphore>,
+    provider: Option<Arc<dyn AssetProvider>>,
+    request_id: u32,
+    uri: String,
+) -> anyhow::Result<()> {
+    let Ok(permit) = fetches.clone().try_acquire_owned() else {
+        log::debug!(
+            "Rejecting asset request {}: too many in flight.",
+            request_id
+        );
+        return reject_fetch_asset(
+            tx,
+            request_id,
+            &format!(
+                "Too many asset requests, at most {} are fetched at once.",
+                MAX_CONCURRENT_FETCHES
+            ),
+        );
+    };
+    let tx = tx.clone();
+    tokio::spawn(async move {
+        let _permit = permit;
+        let fetched = pin!(async {
+            match provider {
+                Some(provider) => provider.fetch(&uri).await,
+                None => Err(anyhow!("Assets are not supported by this server.")),
+            }
+        });
+        let result = match future::select(fetched, pin!(tx.closed())).await {
+            Either::Left((result, _)) => result,
+            Either::Right(_) => {
+                log::debug!("Abandoning asset request {}: client gone.", request_id);
+                return;
+            }
+        };
+        if let Err(err) = &result {
+            log::debug!("Failed to fetch asset {}: {}.", uri, err);
+        }
+        let sent = match build_fetch_asset_response(request_id, &result) {
+            Ok(message) => tx.reply(message),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = sent {
+            log::error!("Failed to reply to asset request {}: {}.", request_id, err);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Creates an asset root with a mesh of package `robot` and a secret file outside the root.
+    fn provider() -> (TempDir, FileSystemAssetProvider) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("root");
+        fs::create_dir_all(root.join("robot/meshes")).unwrap();
+        fs::write(root.join("robot/meshes/base.stl"), b"mesh").unwrap();
+        fs::write(dir.path().join("secret.txt"), b"secret").unwrap();
+        (dir, FileSystemAssetProvider::new(root))
+    }
+
+    /// Fetches `uri` and returns the error and the data of the response sent to the client.
+    async fn fetch(provider: &FileSystemAssetProvider, uri: &str) -> (Option<String>, Vec<u8>) {
+        let result = provider.fetch(uri).await;
+        let message = build_fetch_asset_response(7, &result).unwrap();
+        let bytes = message.as_bytes();
+        assert_eq!(bytes[0], 4);
+        assert_eq!(bytes[1..5], 7_u32.to_le_bytes());
+        let error_len = u32::from_le_bytes(bytes[6..10].try_into().unwrap()) as usize;
+        let error = String::from_utf8(bytes[10..10 + error_len].to_vec()).unwrap();
+        let data = bytes[10 + error_len..].to_vec();
+        match bytes[5] {
+            0 => (None, data),
+            1 => (Some(error), data),
+            status => panic!("Invalid status {}.", status),
+        }
+    }
+
+    async fn assert_rejected(provider: &FileSystemAssetProvider, uri: &str) {
+        let (error, data) = fetch(provider, uri).await;
+        assert!(error.is_some(), "{} was served", uri);
+        assert!(data.is_empty(), "{} was served", uri);
+    }
+
+    #[tokio::test]
+    async fn serves_files_inside_root() {
+        let (dir, provider) = provider();
+        let absolute = format!(
+            "file://{}",
+            dir.path().join("root/robot/meshes/base.stl").display()
+        );
+        for uri in [
+            "package://robot/meshes/base.stl",
+            "package://robot/./meshes/base.stl",
+            "robot/meshes/base.stl",
+            "file://robot/meshes/base.stl",
+            &absolute,
+        ] {
+            assert_eq!(
+                fetch(&provider, uri).await,
+                (None, b"mesh".to_vec()),
+                "{}",
+                uri
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_parent_directories() {
+        let (_dir, provider) = provider();
+        for uri in [
+            "package://../secret.txt",
+            "package://robot/../../secret.txt",
+            "package://robot/meshes/../../../secret.txt",
+            "../secret.txt",
+            "file://../secret.txt",
+        ] {
+            assert_rejected(&provider, uri).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_absolute_paths_outside_root() {
+        let (dir, provider) = provider();
+        let secret = format!("file://{}", dir.path().join("secret.txt").display());
+        for uri in [
+            "file:///etc/passwd",
+            &secret,
+            "package://robot//etc/passwd",
+            "/etc/passwd",
+        ] {
+            assert_rejected(&provider, uri).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn does_not_decode_percent_encoded_segments() {
+        let (_dir, provider) = provider();
+        for uri in [
+            "package://robot/%2e%2e/%2e%2e/secret.txt",
+            "package://%2e%2e/secret.txt",
+            "file://%2e%2e/secret.txt",
+            "%2E%2E/secret.txt",
+        ] {
+            assert_rejected(&provider, uri).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_other_schemes() {
+        let (_dir, provider) = provider();
+        assert_rejected(&provider, "http://example.com/secret.txt").await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn rejects_symlinks_leaving_root() {
+        let (dir, provider) = provider();
+        let root = dir.path().join("root");
+        std::os::unix::fs::symlink(dir.path().join("secret.txt"), root.join("robot/secret.txt"))
+            .unwrap();
+        std::os::unix::fs::symlink(dir.path(), root.join("robot/outside")).unwrap();
+        std::os::unix::fs::symlink(dir.path(), root.join("outside")).unwrap();
+        for uri in [
+            "package://robot/secret.txt",
+            "package://robot/outside/secret.txt",
+            "package://outside/secret.txt",
+            "robot/outside/secret.txt",
+            "file://robot/secret.txt",
+        ] {
+            assert_rejected(&provider, uri).await;
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn serves_symlinks_inside_root() {
+        let (dir, provider) = provider();
+        let root = dir.path().join("root");
+        std::os::unix::fs::symlink(
+            root.join("robot/meshes/base.stl"),
+            root.join("robot/link.stl"),
+        )
+        .unwrap();
+        assert_eq!(
+            fetch(&provider, "package://robot/link.stl").await,
+            (None, b"mesh".to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn confines_registered_packages_to_their_directory() {
+        let (dir, provider) = provider();
+        let provider = provider.with_package("robot", dir.path().join("root/robot/meshes"));
+        assert_eq!(
+            fetch(&provider, "package://robot/base.stl").await,
+            (None, b"mesh".to_vec())
+        );
+        assert_rejected(&provider, "package://robot/../meshes/base.stl").await;
+        assert_rejected(&provider, "package://robot/../../../secret.txt").await;
+    }
+
+    /// Never finishes fetching.
+    struct PendingProvider;
+
+    impl AssetProvider for PendingProvider {
+        fn fetch<'a>(&'a self, _uri: &'a str) -> BoxFuture<'a, anyhow::Result<Vec<u8>>> {
+            Box::pin(future::pending())
+        }
+    }
+
+    #[tokio::test]
+    async fn limits_fetches_in_flight() {
+        let tx = Arc::new(ClientQueue::new(16, Default::default()));
+        let fetches = Arc::new(Semaphore::new(MAX_CONCURRENT_FETCHES));
+        let provider: Arc<dyn AssetProvider> = Arc::new(PendingProvider);
+        for request_id in 0..=MAX_CONCURRENT_FETCHES as u32 {
+            fetch_asset(
+                &tx,
+                &fetches,
+                Some(provider.clone()),
+                request_id,
+                "a".into(),
+            )
+            .unwrap();
+        }
+        assert_eq!(fetches.available_permits(), 0);
+
+        // Only the request beyond the limit is answered, with an error.
+        let (message, _) = tx.pop().await.unwrap();
+        let bytes = message.as_bytes();
+        assert_eq!(bytes[1..5], (MAX_CONCURRENT_FETCHES as u32).to_le_bytes());
+        assert_eq!(bytes[5], 1);
+
+        // Closing the queue abandons the outstanding fetches.
+        tx.close();
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while fetches.available_permits() < MAX_CONCURRENT_FETCHES {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+}
@@ -0,0 +1,312 @@
+//! Authentication of clients while they connect and authorization of their requests.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    convert::Infallible,
+    fmt,
+    net::SocketAddr,
+    sync::Arc,
+};
+
+use futures_util::future::BoxFuture;
+use warp::{
+    http::{HeaderMap, StatusCode},
+    Filter,
+};
+
+/// Who a client is, as established by the authenticator set with
+/// [`crate::FoxgloveWebSocketBuilder::authenticator`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Identity {
+    /// Name of the user or device.
+    pub name: String,
+    /// Roles of the client, e.g. `operator` or `guest`.
+    pub roles: BTreeSet<String>,
+}
+
+impl Identity {
+    /// Creates an identity without roles.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            roles: BTreeSet::new(),
+        }
+    }
+
+    /// Adds a role.
+    pub fn with_role(mut self, role: &str) -> Self {
+        self.roles.insert(role.to_owned());
+        self
+    }
+
+    /// Returns whether the client has the given role.
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.contains(role)
+    }
+}
+
+/// The WebSocket upgrade request of a connecting client.
+#[derive(Clone, Debug)]
+pub struct ConnectionRequest {
+    headers: HeaderMap,
+    query: HashMap<String, String>,
+    remote_addr: Option<SocketAddr>,
+}
+
+impl ConnectionRequest {
+    pub(crate) fn new(
+        headers: HeaderMap,
+        query: HashMap<String, String>,
+        remote_addr: Option<SocketAddr>,
+    ) -> Self {
+        Self {
+            headers,
+            query,
+            remote_addr,
+        }
+    }
+
+    /// Returns the headers of the request.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Returns the value of a header, if it is present and valid UTF-8.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)?.to_str().ok()
+    }
+
+    /// Returns the decoded value of a query string parameter, e.g. of `token` in
+    /// `ws://robot:8765/?token=secret`.
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query.get(name).map(String::as_str)
+    }
+
+    /// Returns the bearer token of the `Authorization` header or else the `token` query string
+    /// parameter. Browsers cannot set headers on WebSocket connections, so Foxglove can only pass
+    /// tokens in the query string.
+    pub fn token(&self) -> Option<&str> {
+        self.header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .or_else(|| self.query_param("token"))
+    }
+
+    /// Returns the address of the client, if known.
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.remote_addr
+    }
+}
+
+/// Refusal of a connection by the authenticator. The client gets the status and message as the
+/// HTTP response to its upgrade request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuthRejection {
+    status: StatusCode,
+    message: String,
+}
+
+impl AuthRejection {
+    /// Creates a rejection with the given HTTP status.
+    pub fn new(status: StatusCode, message: &str) -> Self {
+        Self {
+            status,
+            message: message.to_owned(),
+        }
+    }
+
+    /// Rejects a client that did not authenticate or failed to, with status 401.
+    pub fn unauthorized(message: &str) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, message)
+    }
+
+    /// Rejects an authenticated client that may not connect, with status 403.
+    pub fn forbidden(message: &str) -> Self {
+        Self::new(StatusCode::FORBIDDEN, message)
+    }
+
+    /// Returns the HTTP status of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Returns the message of the response.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for AuthRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.status)
+    }
+}
+
+impl std::error::Error for AuthRejection {}
+
+pub(crate) type AuthenticateFn =
+    dyn Fn(ConnectionRequest) -> BoxFuture<'static, Result<Identity, AuthRejection>> + Send + Sync;
+
+#[derive(Clone)]
+pub(crate) struct Authenticator(pub(crate) Arc<AuthenticateFn>);
+
+impl fmt::Debug for Authenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Authenticator").finish_non_exhaustive()
+    }
+}
+
+/// A request of a client that is checked by the [`AuthorizationPolicy`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation<'a> {
+    /// Subscribe to a channel of the server.
+    Subscribe {
+        /// Topic of the channel.
+        topic: &'a str,
+    },
+    /// Advertise a channel to publish messages on.
+    Advertise {
+        /// Topic of the channel.
+        topic: &'a str,
+    },
+    /// Set the value of a parameter.
+    SetParameter {
+        /// Name of the parameter.
+        name: &'a str,
+    },
+    /// Call a service.
+    CallService {
+        /// Name of the service.
+        service: &'a str,
+    },
+    /// Fetch an asset.
+    FetchAsset {
+        /// URI of the asset.
+        uri: &'a str,
+    },
+}
+
+impl fmt::Display for Operation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Subscribe { topic } => write!(f, "subscribe to {}", topic),
+            Operation::Advertise { topic } => write!(f, "advertise {}", topic),
+            Operation::SetParameter { name } => write!(f, "set parameter {}", name),
+            Operation::CallService { service } => write!(f, "call service {}", service),
+            Operation::FetchAsset { uri } => write!(f, "fetch asset {}", uri),
+        }
+    }
+}
+
+/// Decides which requests a client may make, e.g. based on the roles of its [`Identity`].
+///
+/// Denied requests are not carried out and the client is shown an error status instead.
+/// Closures taking the identity and the operation implement this trait as well.
+///
+/// # Example
+///
+/// ```
+/// use foxglove_ws::{FoxgloveWebSocket, Identity, Operation};
+///
+/// let server = FoxgloveWebSocket::builder()
+///     .authorization_policy(|identity: Option<&Identity>, operation: &Operation| {
+///         match identity {
+///             Some(identity) if identity.has_role("operator") => true,
+///             // Guests may only watch the cameras.
+///             _ => matches!(operation, Operation::Subscribe { topic } if topic.starts_with("/camera/")),
+///         }
+///     })
+///     .build();
+/// ```
+pub trait AuthorizationPolicy: Send + Sync + 'static {
+    /// Returns whether a client may carry out `operation`.
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - Identity of the client, `None` if no authenticator is set.
+    /// * `operation` - Requested operation.
+    fn authorize(&self, identity: Option<&Identity>, operation: &Operation<'_>) -> bool;
+}
+
+impl<F> AuthorizationPolicy for F
+where
+    F: Fn(Option<&Identity>, &Operation<'_>) -> bool + Send + Sync + 'static,
+{
+    fn authorize(&self, identity: Option<&Identity>, operation: &Operation<'_>) -> bool {
+        self(identity, operation)
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Policy(pub(crate) Arc<dyn AuthorizationPolicy>);
+
+impl fmt::Debug for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Policy").finish_non_exhaustive()
+    }
+}
+
+/// Extracts the parts of the upgrade request that the authenticator gets to see.
+///
+/// warp only knows the address of connections it accepted itself, for other connections it is
+/// taken from a [`SocketAddr`] in the extensions of the request.
+pub(crate) fn connection_request(
+) -> impl Filter<Extract = (ConnectionRequest,), Error = Infallible> + Clone {
+    warp::header::headers_cloned()
+        .and(
+            warp::query::<HashMap<String, String>>()
+                .or(warp::any().map(HashMap::new))
+                .unify(),
+        )
+        .and(warp::addr::remote())
+        .and(warp::ext::optional::<SocketAddr>())
+        .map(
+            |headers, query, remote_addr: Option<SocketAddr>, attached: Option<SocketAddr>| {
+                ConnectionRequest::new(headers, query, remote_addr.or(attached))
+            },
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use warp::http::HeaderValue;
+
+    use super::*;
+
+    fn request(authorization: Option<&str>, token: Option<&str>) -> ConnectionRequest {
+        let mut headers = HeaderMap::new();
+        if let Some(authorization) = authorization {
+            headers.insert(
+                "authorization",
+                HeaderValue::from_str(authorization).unwrap(),
+            );
+        }
+        let query = token
+            .map(|token| [("token".to_owned(), token.to_owned())].into())
+            .unwrap_or_default();
+        ConnectionRequest::new(headers, query, None)
+    }
+
+    #[test]
+    fn takes_the_token_from_a_bearer_header() {
+        assert_eq!(request(Some("Bearer secret"), None).token(), Some("secret"));
+        // Other schemes carry no bearer token.
+        assert_eq!(request(Some("Basic c2VjcmV0"), None).token(), None);
+    }
+
+    #[test]
+    fn takes_the_token_from_the_query_string() {
+        assert_eq!(request(None, Some("secret")).token(), Some("secret"));
+        assert_eq!(request(None, None).token(), None);
+    }
+
+    #[test]
+    fn prefers_the_header_over_the_query_string() {
+        let both = request(Some("Bearer header"), Some("query"));
+        assert_eq!(both.token(), Some("header"));
+        assert_eq!(both.query_param("token"), Some("query"));
+
+        // The query string is used if the header holds no bearer token.
+        let basic = request(Some("Basic c2VjcmV0"), Some("query"));
+        assert_eq!(basic.token(), Some("query"));
+    }
+}
@@ -0,0 +1,128 @@
+//! Messages that connected clients publish to the server (`clientPublish` capability).
+
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures_util::Stream;
+use tokio::sync::{mpsc, RwLock};
+use tokio_stream::wrappers::ReceiverStream;
+use uuid::Uuid;
+
+use crate::{protocol_types::ClientAdvertiseChannelMessage, Identity};
+
+/// A channel that a client advertised to publish messages on.
+#[derive(Clone, Debug)]
+pub struct ClientChannel {
+    /// Name of the topic the client publishes on.
+    pub topic: String,
+    /// Message encoding of this channel, e.g. `json` or `ros1`.
+    pub encoding: String,
+    /// Name of the schema.
+    pub schema_name: String,
+    /// Schema describing the message format, if the client sent one.
+    pub schema: Option<String>,
+    /// Encoding of the schema, if the client sent one.
+    pub schema_encoding: Option<String>,
+}
+
+impl From<ClientAdvertiseChannelMessage> for ClientChannel {
+    fn from(message: ClientAdvertiseChannelMessage) -> Self {
+        Self {
+            topic: message.topic,
+            encoding: message.encoding,
+            schema_name: message.schema_name,
+            schema: message.schema,
+            schema_encoding: message.schema_encoding,
+        }
+    }
+}
+
+/// A message a client published on one of its advertised channels.
+#[derive(Clone, Debug)]
+pub struct ClientPublishedMessage {
+    /// ID of the client that published this message.
+    pub client_id: Uuid,
+    /// Identity of the client that published this message, if an authenticator is set.
+    pub identity: Option<Identity>,
+    /// Channel the message was published on.
+    pub channel: Arc<ClientChannel>,
+    /// Encoded message data. Decode it according to the channel's encoding and schema.
+    pub data: Vec<u8>,
+}
+
+/// Stream of messages that clients publish on a topic.
+///
+/// Created by [`crate::FoxgloveWebSocket::subscribe_client_messages`].
+#[derive(Debug)]
+pub struct ClientMessageStream {
+    rx: ReceiverStream<ClientPublishedMessage>,
+}
+
+impl Stream for ClientMessageStream {
+    type Item = ClientPublishedMessage;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.rx).poll_next(cx)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ClientPublishState {
+    subscribers: RwLock<HashMap<String, Vec<mpsc::Sender<ClientPublishedMessage>>>>,
+    /// Number of client messages that are buffered per subscriber before new messages get
+    /// dropped.
+    queue_size: usize,
+}
+
+impl ClientPublishState {
+    pub(crate) fn new(queue_size: usize) -> Self {
+        Self {
+            subscribers: RwLock::default(),
+            queue_size,
+        }
+    }
+
+    pub(crate) async fn subscribe(&self, topic: &str) -> ClientMessageStream {
+        let (tx, rx) = mpsc::channel(self.queue_size);
+        self.subscribers
+            .write()
+            .await
+            .entry(topic.to_owned())
+            .or_default()
+            .push(tx);
+        ClientMessageStream {
+            rx: ReceiverStream::new(rx),
+        }
+    }
+
+    /// Hands a client message to all subscribers of its topic. Subscribers that went away are
+    /// removed, subscribers that are not keeping up miss the message.
+    pub(crate) async fn dispatch(&self, message: ClientPublishedMessage) {
+        let mut subscribers = self.subscribers.write().await;
+        let Some(topic_subscribers) = subscribers.get_mut(&message.channel.topic) else {
+            log::debug!(
+                "Dropping client message on {}: no subscribers.",
+                message.channel.topic
+            );
+            return;
+        };
+        topic_subscribers.retain(|tx| match tx.try_send(message.clone()) {
+            Ok(()) => true,
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                log::debug!(
+                    "Dropping client message on {}: subscriber queue full.",
+                    message.channel.topic
+                );
+                true
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+        });
+        if topic_subscribers.is_empty() {
+            subscribers.remove(&message.channel.topic);
+        }
+    }
+}
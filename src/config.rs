@@ -0,0 +1,402 @@
+//! Configuration of the server, set up through [`FoxgloveWebSocketBuilder`].
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    future::Future,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::anyhow;
+use futures_util::FutureExt;
+use uuid::Uuid;
+
+use crate::{
+    auth::{Authenticator, Policy},
+    AuthRejection, AuthorizationPolicy, BackpressurePolicy, ConnectionRequest, FoxgloveWebSocket,
+    Identity,
+};
+
+/// Optional protocol features that a server announces to its clients.
+///
+/// Only [`Capability::Parameters`] and [`Capability::ParametersSubscribe`] are enabled by default.
+/// The capabilities of other features are enabled once the server uses them, e.g. [`Capability::Services`] when a service is
+/// advertised, unless they were disabled with
+/// [`FoxgloveWebSocketBuilder::disable_capability`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Capability {
+    /// Clients can get and set parameters.
+    Parameters,
+    /// Clients can subscribe to parameter updates.
+    ParametersSubscribe,
+    /// Clients can publish messages to the server. Enabled by
+    /// [`FoxgloveWebSocket::subscribe_client_messages`].
+    ClientPublish,
+    /// Clients can call services. Enabled by [`FoxgloveWebSocket::advertise_service`].
+    Services,
+    /// The server broadcasts its time. Enabled by [`FoxgloveWebSocket::broadcast_time`] and only
+    /// announced once time was broadcast.
+    Time,
+    /// Clients can subscribe to the connection graph. Has to be enabled with the builder.
+    ConnectionGraph,
+    /// Clients can fetch assets. Enabled by [`FoxgloveWebSocket::set_asset_provider`] and only
+    /// announced once an asset provider is set.
+    Assets,
+}
+
+impl Capability {
+    /// All capabilities, in the order in which they are announced.
+    pub const ALL: [Capability; 7] = [
+        Capability::Parameters,
+        Capability::ParametersSubscribe,
+        Capability::ClientPublish,
+        Capability::Services,
+        Capability::Time,
+        Capability::ConnectionGraph,
+        Capability::Assets,
+    ];
+
+    /// Name of the capability in the protocol.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Capability::Parameters => "parameters",
+            Capability::ParametersSubscribe => "parametersSubscribe",
+            Capability::ClientPublish => "clientPublish",
+            Capability::Services => "services",
+            Capability::Time => "time",
+            Capability::ConnectionGraph => "connectionGraph",
+            Capability::Assets => "assets",
+        }
+    }
+}
+
+/// Encodings that clients may publish in by default.
+const DEFAULT_SUPPORTED_ENCODINGS: [&str; 3] = ["json", "ros1", "cdr"];
+
+/// Number of messages buffered per subscription of a client by default.
+const DEFAULT_CLIENT_QUEUE_SIZE: usize = 10;
+
+/// Number of client messages buffered per subscriber of
+/// [`FoxgloveWebSocket::subscribe_client_messages`] by default.
+const DEFAULT_CLIENT_MESSAGE_QUEUE_SIZE: usize = 100;
+
+#[derive(Clone, Debug)]
+pub(crate) struct ServerConfig {
+    pub(crate) name: String,
+    pub(crate) metadata: HashMap<String, String>,
+    pub(crate) supported_encodings: Vec<String>,
+    /// Capabilities enabled from the start.
+    pub(crate) capabilities: BTreeSet<Capability>,
+    /// Capabilities that stay disabled even when the server uses their feature.
+    pub(crate) disabled_capabilities: BTreeSet<Capability>,
+    pub(crate) client_queue_size: usize,
+    pub(crate) client_message_queue_size: usize,
+    pub(crate) backpressure: BackpressurePolicy,
+    pub(crate) session_id: String,
+    pub(crate) authenticator: Option<Authenticator>,
+    pub(crate) authorization_policy: Option<Policy>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            metadata: HashMap::new(),
+            supported_encodings: DEFAULT_SUPPORTED_ENCODINGS.map(String::from).to_vec(),
+            capabilities: [Capability::Parameters, Capability::ParametersSubscribe].into(),
+            disabled_capabilities: BTreeSet::new(),
+            client_queue_size: DEFAULT_CLIENT_QUEUE_SIZE,
+            client_message_queue_size: DEFAULT_CLIENT_MESSAGE_QUEUE_SIZE,
+            backpressure: BackpressurePolicy::default(),
+            session_id: String::new(),
+            authenticator: None,
+            authorization_policy: None,
+        }
+    }
+}
+
+/// Capabilities of a running server. Capabilities are enabled as the server uses their features.
+#[derive(Debug)]
+pub(crate) struct Capabilities {
+    enabled: RwLock<BTreeSet<Capability>>,
+    disabled: BTreeSet<Capability>,
+}
+
+impl Capabilities {
+    pub(crate) fn new(config: &ServerConfig) -> Self {
+        Self {
+            enabled: RwLock::new(config.capabilities.clone()),
+            disabled: config.disabled_capabilities.clone(),
+        }
+    }
+
+    pub(crate) fn contains(&self, capability: Capability) -> bool {
+        self.enabled.read().unwrap().contains(&capability)
+    }
+
+    /// Returns the enabled capabilities.
+    pub(crate) fn enabled(&self) -> BTreeSet<Capability> {
+        self.enabled.read().unwrap().clone()
+    }
+
+    /// Enables a capability. Fails if the capability was disabled with the builder.
+    pub(crate) fn enable(&self, capability: Capability) -> anyhow::Result<()> {
+        if self.disabled.contains(&capability) {
+            return Err(anyhow!(
+                "The {} capability is disabled.",
+                capability.as_str()
+            ));
+        }
+        self.enabled.write().unwrap().insert(capability);
+        Ok(())
+    }
+}
+
+/// Builder for a [`FoxgloveWebSocket`] with a custom configuration.
+///
+/// # Example
+///
+/// ```
+/// use foxglove_ws::{Capability, FoxgloveWebSocket};
+///
+/// let server = FoxgloveWebSocket::builder()
+///     .name("robot")
+///     .metadata("robot_id", "r2d2")
+///     .metadata("version", env!("CARGO_PKG_VERSION"))
+///     .supported_encodings(["json"])
+///     .enable_capability(Capability::ConnectionGraph)
+///     .disable_capability(Capability::ClientPublish)
+///     .build();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct FoxgloveWebSocketBuilder {
+    config: ServerConfig,
+    /// Session ID, generated when building if not set, so that servers built from clones of a
+    /// builder have their own.
+    session_id: Option<String>,
+}
+
+impl FoxgloveWebSocketBuilder {
+    /// Creates a builder with the default configuration: only the [`Capability::Parameters`] and
+    /// [`Capability::ParametersSubscribe`] capabilities are enabled from the start, the other
+    /// capabilities are enabled as the server uses their features, and clients may publish in
+    /// `json`, `ros1` and `cdr`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the server name shown in Foxglove.
+    pub fn name(mut self, name: &str) -> Self {
+        self.config.name = name.to_owned();
+        self
+    }
+
+    /// Adds a metadata entry that is sent to clients, e.g. the robot ID or the software version.
+    pub fn metadata(mut self, key: &str, value: &str) -> Self {
+        self.config
+            .metadata
+            .insert(key.to_owned(), value.to_owned());
+        self
+    }
+
+    /// Sets the encodings that clients may publish messages in.
+    pub fn supported_encodings<I, S>(mut self, encodings: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.supported_encodings = encodings.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the capabilities, replacing the defaults. All other capabilities are disabled, even
+    /// when the server uses their features.
+    pub fn capabilities(mut self, capabilities: impl IntoIterator<Item = Capability>) -> Self {
+        self.config.capabilities = capabilities.into_iter().collect();
+        self.config.disabled_capabilities = Capability::ALL
+            .into_iter()
+            .filter(|capability| !self.config.capabilities.contains(capability))
+            .collect();
+        self
+    }
+
+    /// Enables a capability from the start.
+    pub fn enable_capability(mut self, capability: Capability) -> Self {
+        self.config.capabilities.insert(capability);
+        self.config.disabled_capabilities.remove(&capability);
+        self
+    }
+
+    /// Disables a capability, even when the server uses its feature. Requests of clients that
+    /// need it are ignored.
+    pub fn disable_capability(mut self, capability: Capability) -> Self {
+        self.config.capabilities.remove(&capability);
+        self.config.disabled_capabilities.insert(capability);
+        self
+    }
+
+    /// Sets the number of messages buffered per client for each subscription, unless the channel
+    /// has its own queue size. At least one message is buffered.
+    pub fn client_queue_size(mut self, size: usize) -> Self {
+        self.config.client_queue_size = size.max(1);
+        self
+    }
+
+    /// Sets the number of client messages buffered per subscriber of
+    /// [`FoxgloveWebSocket::subscribe_client_messages`]. At least one message is buffered.
+    pub fn client_message_queue_size(mut self, size: usize) -> Self {
+        self.config.client_message_queue_size = size.max(1);
+        self
+    }
+
+    /// Sets what happens to messages for clients that do not keep up, unless their channel has
+    /// its own policy. Defaults to [`BackpressurePolicy::DropNewest`].
+    pub fn backpressure(mut self, backpressure: BackpressurePolicy) -> Self {
+        self.config.backpressure = backpressure;
+        self
+    }
+
+    /// Sets the session ID. Clients use it to tell a restarted server apart. Defaults to a random
+    /// ID.
+    pub fn session_id(mut self, session_id: &str) -> Self {
+        self.session_id = Some(session_id.to_owned());
+        self
+    }
+
+    /// Sets the authenticator that decides whether a client may connect and who it is.
+    ///
+    /// The authenticator runs during the WebSocket upgrade and sees the request's headers, query
+    /// string and the client's address. The returned identity is handed to the other hooks of
+    /// the server, e.g. with [`crate::ServiceRequest::identity`]. A rejection is sent back as the
+    /// HTTP response. Without an authenticator, all clients may connect and have no identity.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use foxglove_ws::{AuthRejection, FoxgloveWebSocket, Identity};
+    ///
+    /// let server = FoxgloveWebSocket::builder()
+    ///     .authenticator(|request| async move {
+    ///         match request.token() {
+    ///             Some("operator-secret") => Ok(Identity::new("operator").with_role("operator")),
+    ///             Some(_) => Err(AuthRejection::forbidden("Invalid token.")),
+    ///             None => Ok(Identity::new("guest").with_role("guest")),
+    ///         }
+    ///     })
+    ///     .build();
+    /// ```
+    pub fn authenticator<F, Fut>(mut self, authenticator: F) -> Self
+    where
+        F: Fn(ConnectionRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Identity, AuthRejection>> + Send + 'static,
+    {
+        self.config.authenticator = Some(Authenticator(Arc::new(move |request| {
+            authenticator(request).boxed()
+        })));
+        self
+    }
+
+    /// Sets the policy that decides which requests clients may make, e.g. which topics they may
+    /// subscribe to. Without a policy, all requests are allowed.
+    pub fn authorization_policy(mut self, policy: impl AuthorizationPolicy) -> Self {
+        self.config.authorization_policy = Some(Policy(Arc::new(policy)));
+        self
+    }
+
+    /// Creates the server.
+    pub fn build(mut self) -> FoxgloveWebSocket {
+        self.config.session_id = self
+            .session_id
+            .unwrap_or_else(|| Uuid::new_v4().as_hyphenated().to_string());
+        FoxgloveWebSocket::with_config(self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FileSystemAssetProvider, ServiceSchema};
+
+    fn enabled(server: &FoxgloveWebSocket) -> Vec<Capability> {
+        server.capabilities.enabled().into_iter().collect()
+    }
+
+    async fn advertise_service(server: &FoxgloveWebSocket) -> anyhow::Result<()> {
+        let schema = || ServiceSchema::new("json", "Empty", "{}", "jsonschema");
+        let service = server
+            .advertise_service("/reset", "Empty", schema(), schema(), |_| async {
+                Ok(Vec::new())
+            })
+            .await?;
+        service.unadvertise().await
+    }
+
+    #[tokio::test]
+    async fn enables_capabilities_as_features_are_used() {
+        let server = FoxgloveWebSocket::new("robot");
+        assert_eq!(
+            enabled(&server),
+            [Capability::Parameters, Capability::ParametersSubscribe]
+        );
+
+        advertise_service(&server).await.unwrap();
+        server
+            .set_asset_provider(FileSystemAssetProvider::new("."))
+            .await;
+        server.subscribe_client_messages("/topic").await;
+        server.broadcast_time(1).await.unwrap();
+        assert_eq!(
+            enabled(&server),
+            [
+                Capability::Parameters,
+                Capability::ParametersSubscribe,
+                Capability::ClientPublish,
+                Capability::Services,
+                Capability::Time,
+                Capability::Assets,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_disabled_capabilities_disabled() {
+        let server = FoxgloveWebSocket::builder()
+            .disable_capability(Capability::Services)
+            .disable_capability(Capability::Assets)
+            .enable_capability(Capability::ConnectionGraph)
+            .build();
+        assert!(advertise_service(&server).await.is_err());
+        server
+            .set_asset_provider(FileSystemAssetProvider::new("."))
+            .await;
+        server.broadcast_time(1).await.unwrap();
+        assert_eq!(
+            enabled(&server),
+            [
+                Capability::Parameters,
+                Capability::ParametersSubscribe,
+                Capability::Time,
+                Capability::ConnectionGraph
+            ]
+        );
+
+        let server = FoxgloveWebSocket::builder()
+            .capabilities([Capability::Services])
+            .build();
+        advertise_service(&server).await.unwrap();
+        assert!(server.broadcast_time(1).await.is_err());
+        assert_eq!(enabled(&server), [Capability::Services]);
+    }
+
+    #[test]
+    fn generates_a_session_id_per_server() {
+        let builder = FoxgloveWebSocket::builder().name("robot");
+        let first = builder.clone().build();
+        let second = builder.build();
+        assert!(!first.config.session_id.is_empty());
+        assert_ne!(first.config.session_id, second.config.session_id);
+
+        let builder = FoxgloveWebSocket::builder().session_id("session");
+        assert_eq!(builder.clone().build().config.session_id, "session");
+        assert_eq!(builder.build().config.session_id, "session");
+    }
+}
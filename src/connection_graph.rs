@@ -0,0 +1,424 @@
+//! Graph of publishers, subscribers and services (`connectionGraph` capability).
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::Arc,
+};
+
+use tokio::sync::RwLock;
+use uuid::Uuid;
+use warp::ws::Message;
+
+use crate::{
+    config::Capabilities, protocol_types::*, services::ServiceState, Capability, ChannelState,
+    ClientState,
+};
+
+/// Maps topic or service names to the IDs of the nodes connected to them.
+type NameToIds = BTreeMap<String, BTreeSet<String>>;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Graph {
+    published_topics: NameToIds,
+    subscribed_topics: NameToIds,
+    advertised_services: NameToIds,
+}
+
+fn insert(map: &mut NameToIds, name: &str, id: &str) {
+    map.entry(name.to_owned())
+        .or_default()
+        .insert(id.to_owned());
+}
+
+fn remove(map: &mut NameToIds, name: &str, id: &str) {
+    if let Some(ids) = map.get_mut(name) {
+        ids.remove(id);
+        if ids.is_empty() {
+            map.remove(name);
+        }
+    }
+}
+
+/// Returns the entries of `new` that differ from `old`. Names that are only left in `old` are
+/// reported with an empty list of IDs.
+fn changed_entries(old: &NameToIds, new: &NameToIds) -> Vec<(String, Vec<String>)> {
+    let changed = new
+        .iter()
+        .filter(|(name, ids)| old.get(*name) != Some(ids))
+        .map(|(name, ids)| (name.clone(), ids.iter().cloned().collect()));
+    let emptied = old
+        .keys()
+        .filter(|name| !new.contains_key(*name))
+        .map(|name| (name.clone(), vec![]));
+    changed.chain(emptied).collect()
+}
+
+impl Graph {
+    /// Computes the update message that turns `self` into `new`.
+    fn diff(&self, new: &Graph) -> ServerMessage {
+        let topic_exists = |graph: &Graph, name: &String| {
+            graph.published_topics.contains_key(name) || graph.subscribed_topics.contains_key(name)
+        };
+        let removed_topics: BTreeSet<_> = self
+            .published_topics
+            .keys()
+            .chain(self.subscribed_topics.keys())
+            .filter(|name| !topic_exists(new, name))
+            .cloned()
+            .collect();
+        let removed_services: Vec<_> = self
+            .advertised_services
+            .keys()
+            .filter(|name| !new.advertised_services.contains_key(*name))
+            .cloned()
+            .collect();
+
+        // Removed topics and services are reported separately and not as emptied entries.
+        let published_topics = changed_entries(&self.published_topics, &new.published_topics)
+            .into_iter()
+            .filter(|(name, _)| !removed_topics.contains(name))
+            .map(|(name, publisher_ids)| PublishedTopicMessage {
+                name,
+                publisher_ids,
+            })
+            .collect();
+        let subscribed_topics = changed_entries(&self.subscribed_topics, &new.subscribed_topics)
+            .into_iter()
+            .filter(|(name, _)| !removed_topics.contains(name))
+            .map(|(name, subscriber_ids)| SubscribedTopicMessage {
+                name,
+                subscriber_ids,
+            })
+            .collect();
+        let advertised_services =
+            changed_entries(&self.advertised_services, &new.advertised_services)
+                .into_iter()
+                .filter(|(name, _)| !removed_services.contains(name))
+                .map(|(name, provider_ids)| AdvertisedServiceMessage { name, provider_ids })
+                .collect();
+
+        ServerMessage::ConnectionGraphUpdate {
+            published_topics,
+            subscribed_topics,
+            advertised_services,
+            removed_topics: removed_topics.into_iter().collect(),
+            removed_services,
+        }
+    }
+}
+
+/// Graph of the publishers, subscribers and services connected through this server, as shown by
+/// Foxglove's Topic Graph panel.
+///
+/// The graph is derived from the server's own channels and services and from the clients'
+/// subscriptions and advertisements. Nodes that live outside of this process can be added by
+/// hand. Clients that subscribed to the graph are sent updates whenever it changes.
+#[derive(Clone, Debug)]
+pub struct ConnectionGraph {
+    /// Node ID of this server, used for its own channels and services.
+    server_id: String,
+    /// Nodes registered by hand.
+    external: Arc<RwLock<Graph>>,
+    /// Graph as last sent to the subscribed clients.
+    published: Arc<RwLock<Graph>>,
+
+    clients: Arc<ClientState>,
+    channels: Arc<ChannelState>,
+    services: Arc<ServiceState>,
+    capabilities: Arc<Capabilities>,
+}
+
+impl ConnectionGraph {
+    pub(crate) fn new(
+        server_id: &str,
+        clients: Arc<ClientState>,
+        channels: Arc<ChannelState>,
+        services: Arc<ServiceState>,
+        capabilities: Arc<Capabilities>,
+    ) -> Self {
+        Self {
+            server_id: server_id.to_owned(),
+            external: Arc::default(),
+            published: Arc::default(),
+            clients,
+            channels,
+            services,
+            capabilities,
+        }
+    }
+
+    /// Registers an external publisher on a topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - Name of the topic.
+    /// * `publisher_id` - ID of the publishing node.
+    pub async fn add_publisher(&self, topic: &str, publisher_id: &str) -> anyhow::Result<()> {
+        insert(
+            &mut self.external.write().await.published_topics,
+            topic,
+            publisher_id,
+        );
+        self.update().await
+    }
+
+    /// Removes an external publisher again.
+    pub async fn remove_publisher(&self, topic: &str, publisher_id: &str) -> anyhow::Result<()> {
+        remove(
+            &mut self.external.write().await.published_topics,
+            topic,
+            publisher_id,
+        );
+        self.update().await
+    }
+
+    /// Registers an external subscriber of a topic.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - Name of the topic.
+    /// * `subscriber_id` - ID of the subscribing node.
+    pub async fn add_subscriber(&self, topic: &str, subscriber_id: &str) -> anyhow::Result<()> {
+        insert(
+            &mut self.external.write().await.subscribed_topics,
+            topic,
+            subscriber_id,
+        );
+        self.update().await
+    }
+
+    /// Removes an external subscriber again.
+    pub async fn remove_subscriber(&self, topic: &str, subscriber_id: &str) -> anyhow::Result<()> {
+        remove(
+            &mut self.external.write().await.subscribed_topics,
+            topic,
+            subscriber_id,
+        );
+        self.update().await
+    }
+
+    /// Registers an external service provider.
+    ///
+    /// # Arguments
+    ///
+    /// * `service` - Name of the service.
+    /// * `provider_id` - ID of the node providing the service.
+    pub async fn add_service(&self, service: &str, provider_id: &str) -> anyhow::Result<()> {
+        insert(
+            &mut self.external.write().await.advertised_services,
+            service,
+            provider_id,
+        );
+        self.update().await
+    }
+
+    /// Removes an external service provider again.
+    pub async fn remove_service(&self, service: &str, provider_id: &str) -> anyhow::Result<()> {
+        remove(
+            &mut self.external.write().await.advertised_services,
+            service,
+            provider_id,
+        );
+        self.update().await
+    }
+
+    async fn current_graph(&self) -> Graph {
+        let mut graph = self.external.read().await.clone();
+
+        // Only one lock is held at a time to not get in the way of code that locks the clients
+        // before the channels.
+        let channel_topics: HashMap<_, _> = self
+            .channels
+            .channels
+            .read()
+            .await
+            .iter()
+            .map(|(channel_id, metadata)| (*channel_id, metadata.channel_message.topic.clone()))
+            .collect();
+        for topic in channel_topics.values() {
+            insert(&mut graph.published_topics, topic, &self.server_id);
+        }
+        for service in self.services.services.read().await.values() {
+            insert(
+                &mut graph.advertised_services,
+                &service.service_message.name,
+                &self.server_id,
+            );
+        }
+        for client in self.clients.clients.read().await.values() {
+            let client_id = client.id.as_hyphenated().to_string();
+            for channel_id in client.subscriptions.keys() {
+                if let Some(topic) = channel_topics.get(channel_id) {
+                    insert(&mut graph.subscribed_topics, topic, &client_id);
+                }
+            }
+            for channel in client.advertisements.values() {
+                insert(&mut graph.published_topics, &channel.topic, &client_id);
+            }
+        }
+        graph
+    }
+
+    /// Recomputes the graph and sends the changes to all subscribed clients.
+    ///
+    /// Nothing is done while no client is subscribed. The graph last sent is brought up to date
+    /// once a client subscribes.
+    pub(crate) async fn update(&self) -> anyhow::Result<()> {
+        if !self.capabilities.contains(Capability::ConnectionGraph) {
+            return Ok(());
+        }
+        // Holding the lock for the whole update keeps the updates in order. Clients subscribe
+        // while holding it, so a change is either seen by a new subscriber or sent to it.
+        let mut published = self.published.write().await;
+        let has_subscribers = self
+            .clients
+            .clients
+            .read()
+            .await
+            .values()
+            .any(|client| client.subscribed_connection_graph);
+        if !has_subscribers {
+            return Ok(());
+        }
+        self.publish_changes(&mut published).await
+    }
+
+    async fn publish_changes(&self, published: &mut Graph) -> anyhow::Result<()> {
+        let graph = self.current_graph().await;
+        if graph == *published {
+            return Ok(());
+        }
+        let message = Message::text(serde_json::to_string(&published.diff(&graph))?);
+        *published = graph;
+
+        for client in self.clients.clients.read().await.values() {
+            if client.subscribed_connection_graph {
+                client.broadcast(message.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Subscribes a client to graph updates, starting with the complete graph.
+    pub(crate) async fn subscribe(&self, client_id: &Uuid) -> anyhow::Result<()> {
+        let mut published = self.published.write().await;
+        // Bring the other subscribers up to date first, so that all subscribers continue from
+        // the same graph.
+        self.publish_changes(&mut published).await?;
+
+        let message = Message::text(serde_json::to_string(&Graph::default().diff(&published))?);
+        let mut clients = self.clients.clients.write().await;
+        let client = clients
+            .get_mut(client_id)
+            .ok_or(anyhow::anyhow!("Client gone from client map?"))?;
+        client.subscribed_connection_graph = true;
+        client.tx.reply(message)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn graph(
+        published: &[(&str, &str)],
+        subscribed: &[(&str, &str)],
+        services: &[(&str, &str)],
+    ) -> Graph {
+        let mut graph = Graph::default();
+        for (name, id) in published {
+            insert(&mut graph.published_topics, name, id);
+        }
+        for (name, id) in subscribed {
+            insert(&mut graph.subscribed_topics, name, id);
+        }
+        for (name, id) in services {
+            insert(&mut graph.advertised_services, name, id);
+        }
+        graph
+    }
+
+    fn diff(old: &Graph, new: &Graph) -> serde_json::Value {
+        serde_json::to_value(old.diff(new)).unwrap()
+    }
+
+    #[test]
+    fn reports_added_nodes() {
+        let old = graph(&[("/a", "server")], &[], &[]);
+        let new = graph(
+            &[("/a", "server"), ("/a", "client"), ("/b", "client")],
+            &[("/a", "other")],
+            &[("/reset", "server")],
+        );
+        assert_eq!(
+            diff(&old, &new),
+            json!({
+                "op": "connectionGraphUpdate",
+                "publishedTopics": [
+                    {"name": "/a", "publisherIds": ["client", "server"]},
+                    {"name": "/b", "publisherIds": ["client"]},
+                ],
+                "subscribedTopics": [{"name": "/a", "subscriberIds": ["other"]}],
+                "advertisedServices": [{"name": "/reset", "providerIds": ["server"]}],
+                "removedTopics": [],
+                "removedServices": [],
+            })
+        );
+    }
+
+    #[test]
+    fn reports_removed_nodes() {
+        let old = graph(
+            &[("/a", "server"), ("/a", "client"), ("/b", "client")],
+            &[("/a", "other"), ("/c", "other")],
+            &[
+                ("/reset", "server"),
+                ("/reset", "client"),
+                ("/stop", "server"),
+            ],
+        );
+        let new = graph(&[("/a", "server")], &[], &[("/reset", "server")]);
+        // Topics and services without any node left are removed, the others are updated.
+        assert_eq!(
+            diff(&old, &new),
+            json!({
+                "op": "connectionGraphUpdate",
+                "publishedTopics": [{"name": "/a", "publisherIds": ["server"]}],
+                "subscribedTopics": [{"name": "/a", "subscriberIds": []}],
+                "advertisedServices": [{"name": "/reset", "providerIds": ["server"]}],
+                "removedTopics": ["/b", "/c"],
+                "removedServices": ["/stop"],
+            })
+        );
+    }
+
+    #[test]
+    fn keeps_topics_with_only_subscribers() {
+        let old = graph(&[("/a", "server")], &[("/a", "client")], &[]);
+        let new = graph(&[], &[("/a", "client")], &[]);
+        assert_eq!(
+            diff(&old, &new),
+            json!({
+                "op": "connectionGraphUpdate",
+                "publishedTopics": [{"name": "/a", "publisherIds": []}],
+                "subscribedTopics": [],
+                "advertisedServices": [],
+                "removedTopics": [],
+                "removedServices": [],
+            })
+        );
+    }
+
+    #[test]
+    fn removing_and_adding_again_cancels_out() {
+        let mut graph = graph(&[("/a", "server")], &[], &[]);
+        let old = graph.clone();
+        remove(&mut graph.published_topics, "/a", "server");
+        assert!(graph.published_topics.is_empty());
+        insert(&mut graph.published_topics, "/a", "server");
+        assert_eq!(graph, old);
+    }
+}
@@ -0,0 +1,160 @@
+//! Channels of typed messages that are serialized by their encoding.
+
+use std::fmt;
+
+use crate::{Channel, FoxgloveWebSocket, PublisherOptions, SchemaDescriptor};
+
+/// How messages of a type are encoded on the wire and described to clients.
+///
+/// Message types can implement it for themselves with `Message = Self`. Encodings of other
+/// types are separate types, e.g. [`crate::ros1::Ros1`] for ROS 1 messages, `Json` with the
+/// `json` feature and `Protobuf` with the `protobuf` feature.
+///
+/// <https://mcap.dev/spec/registry> lists the encodings and schema encodings Foxglove supports.
+///
+/// # Example
+///
+/// ```
+/// use foxglove_ws::{MessageEncoding, SchemaDescriptor};
+///
+/// /// A ROS 1 `std_msgs/String`.
+/// struct StringMessage(String);
+///
+/// impl MessageEncoding for StringMessage {
+///     type Message = Self;
+///
+///     fn encoding() -> String {
+///         "ros1".to_owned()
+///     }
+///
+///     fn schema_name() -> String {
+///         "std_msgs/String".to_owned()
+///     }
+///
+///     fn schema() -> anyhow::Result<SchemaDescriptor> {
+///         Ok("string data".into())
+///     }
+///
+///     fn schema_encoding() -> Option<String> {
+///         Some("ros1msg".to_owned())
+///     }
+///
+///     fn encode(message: &Self) -> anyhow::Result<Vec<u8>> {
+///         // ROS 1 strings are encoded as 4 bytes length followed by the bytes.
+///         let mut data = (message.0.len() as u32).to_le_bytes().to_vec();
+///         data.extend_from_slice(message.0.as_bytes());
+///         Ok(data)
+///     }
+/// }
+/// ```
+pub trait MessageEncoding {
+    /// Type of the messages.
+    type Message;
+
+    /// Message encoding of the channel, e.g. `ros1`, `protobuf` or `json`.
+    fn encoding() -> String;
+
+    /// Name of the schema, e.g. `std_msgs/String`.
+    fn schema_name() -> String;
+
+    /// Schema describing the message format. Fails if the schema cannot be built, e.g. because
+    /// the definition of a nested type is missing.
+    fn schema() -> anyhow::Result<SchemaDescriptor>;
+
+    /// Encoding of the schema, e.g. `ros1msg`. May be `None` if it can be deduced from the
+    /// message encoding.
+    fn schema_encoding() -> Option<String>;
+
+    /// Serializes a message.
+    fn encode(message: &Self::Message) -> anyhow::Result<Vec<u8>>;
+}
+
+/// Serializes a message of a typed channel.
+pub(crate) type EncodeFn<T> = fn(&T) -> anyhow::Result<Vec<u8>>;
+
+/// A channel to send messages of type `T` with, created e.g. with
+/// [`FoxgloveWebSocket::create_typed_publisher`].
+pub struct TypedChannel<T> {
+    channel: Channel,
+    encode: EncodeFn<T>,
+}
+
+impl<T> TypedChannel<T> {
+    pub(crate) fn new(channel: Channel, encode: EncodeFn<T>) -> Self {
+        Self { channel, encode }
+    }
+
+    /// Serializes a message and sends it to all subscribed clients for this channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp_ns` - Point in time this message was published/created/logged.
+    /// * `message` - Message to publish.
+    pub async fn send(&self, timestamp_ns: u64, message: &T) -> anyhow::Result<()> {
+        self.channel
+            .send(timestamp_ns, &(self.encode)(message)?)
+            .await
+    }
+
+    /// Unadvertises this channel to all clients.
+    pub async fn unadvertise(self) -> anyhow::Result<()> {
+        self.channel.unadvertise().await
+    }
+}
+
+impl<T> fmt::Debug for TypedChannel<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TypedChannel")
+            .field("channel", &self.channel)
+            .finish()
+    }
+}
+
+impl FoxgloveWebSocket {
+    /// Advertise a new publisher of messages encoded with `E`. The encoding and schema of the
+    /// channel are taken from `E`.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - Name of the topic of this new channel.
+    /// * `is_latching` - Whether messages sent of this channel are sticky. Each newly connecting
+    ///   client will be sent the last sticky message that was sent on this channel.
+    pub async fn create_typed_publisher<E: MessageEncoding>(
+        &self,
+        topic: &str,
+        is_latching: bool,
+    ) -> anyhow::Result<TypedChannel<E::Message>> {
+        self.create_typed_publisher_with_options::<E>(
+            topic,
+            PublisherOptions {
+                is_latching,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// Advertise a new publisher of messages encoded with `E` with additional options.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - Name of the topic of this new channel.
+    /// * `options` - Options of the channel.
+    pub async fn create_typed_publisher_with_options<E: MessageEncoding>(
+        &self,
+        topic: &str,
+        options: PublisherOptions,
+    ) -> anyhow::Result<TypedChannel<E::Message>> {
+        let channel = self
+            .create_publisher_with_options(
+                topic,
+                &E::encoding(),
+                &E::schema_name(),
+                E::schema()?,
+                E::schema_encoding().as_deref(),
+                options,
+            )
+            .await?;
+        Ok(TypedChannel::new(channel, E::encode))
+    }
+}
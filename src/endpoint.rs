@@ -0,0 +1,302 @@
+//! The Foxglove WebSocket endpoint, to be served on its own or mounted into other applications.
+
+use std::{convert::Infallible, future::Future};
+
+use warp::{
+    hyper::{service::Service, Body, Request},
+    reply::Response,
+    ws::Ws,
+    Filter, Rejection, Reply,
+};
+
+use crate::{
+    auth, client_connected, AuthRejection, ConnectionRequest, FoxgloveWebSocket, Identity,
+};
+
+/// Subprotocol of the Foxglove WebSocket protocol.
+const SUBPROTOCOL: &str = "foxglove.websocket.v1";
+
+impl FoxgloveWebSocket {
+    /// Returns the endpoint as a warp filter, to serve it next to other routes of an application.
+    ///
+    /// The filter only matches the end of the path, so it can be mounted below any prefix. All
+    /// filters and services of a server share its state, e.g. clients connected to either see
+    /// the same channels.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # async fn example(server: foxglove_ws::FoxgloveWebSocket) {
+    /// use warp::Filter;
+    ///
+    /// let status = warp::path("status").map(|| "ok");
+    /// let foxglove = warp::path("foxglove").and(server.filter());
+    /// warp::serve(status.or(foxglove))
+    ///     .run(([0, 0, 0, 0], 8080))
+    ///     .await;
+    /// # }
+    /// ```
+    pub fn filter(
+        &self,
+    ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone + Send + Sync + 'static
+    {
+        warp::path::end().and(self.endpoint())
+    }
+
+    /// Returns the endpoint as a filter that accepts any path.
+    fn endpoint(
+        &self,
+    ) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone + Send + Sync + 'static {
+        let server = self.clone();
+        let server = warp::any().map(move || server.clone());
+        warp::ws()
+            .and(server)
+            .and(auth::connection_request())
+            .and_then(
+                |ws: Ws, server: FoxgloveWebSocket, request: ConnectionRequest| async move {
+                    Ok::<_, Infallible>(server.upgrade(ws, request).await)
+                },
+            )
+    }
+
+    /// Authenticates a connecting client and upgrades its connection to a WebSocket.
+    async fn upgrade(&self, ws: Ws, request: ConnectionRequest) -> Response {
+        let identity = match self.authenticate(request).await {
+            Ok(identity) => identity,
+            Err(rejection) => {
+                return warp::reply::with_status(
+                    rejection.message().to_owned(),
+                    rejection.status(),
+                )
+                .into_response();
+            }
+        };
+        let server = self.clone();
+        warp::reply::with_header(
+            ws.on_upgrade(move |socket| client_connected(socket, server, identity)),
+            "Sec-WebSocket-Protocol",
+            SUBPROTOCOL,
+        )
+        .into_response()
+    }
+
+    /// Runs the authenticator, if any, for a connecting client.
+    pub(crate) async fn authenticate(
+        &self,
+        request: ConnectionRequest,
+    ) -> Result<Option<Identity>, AuthRejection> {
+        let Some(authenticator) = &self.config.authenticator else {
+            return Ok(None);
+        };
+        let remote_addr = request.remote_addr();
+        match (authenticator.0)(request).await {
+            Ok(identity) => Ok(Some(identity)),
+            Err(rejection) => {
+                log::info!("Rejected client {:?}: {}.", remote_addr, rejection);
+                Err(rejection)
+            }
+        }
+    }
+
+    /// Returns the endpoint as a hyper service, to serve it from a hyper server or to hand
+    /// requests to it from another hyper service. Requests are accepted on any path.
+    ///
+    /// To let the authenticator see the address of the client, insert it as a
+    /// [`std::net::SocketAddr`] into the extensions of the request.
+    #[cfg(feature = "hyper")]
+    pub fn hyper_service(
+        &self,
+    ) -> impl Service<
+        Request<Body>,
+        Response = Response,
+        Error = Infallible,
+        Future = impl Future<Output = Result<Response, Infallible>> + Send,
+    > + Clone
+           + Send
+           + Sync
+           + 'static {
+        self.service()
+    }
+
+    /// Returns the endpoint as a hyper service, accepting requests on any path.
+    pub(crate) fn service(
+        &self,
+    ) -> impl Service<
+        Request<Body>,
+        Response = Response,
+        Error = Infallible,
+        Future = impl Future<Output = Result<Response, Infallible>> + Send,
+    > + Clone
+           + Send
+           + Sync
+           + 'static {
+        warp::service(self.endpoint())
+    }
+
+    /// Returns the endpoint as a tower service for axum. Requests are accepted on any path.
+    ///
+    /// The authenticator sees the address of the client if the application is served with
+    /// `into_make_service_with_connect_info::<SocketAddr>()`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # async fn example(server: foxglove_ws::FoxgloveWebSocket) -> anyhow::Result<()> {
+    /// use axum::{routing::get, Router};
+    ///
+    /// let app = Router::new()
+    ///     .route("/status", get(|| async { "ok" }))
+    ///     .route_service("/foxglove", server.axum_service());
+    /// # let _: Router = app;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "axum")]
+    pub fn axum_service(&self) -> AxumService {
+        AxumService {
+            server: self.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "axum")]
+pub use axum_service::AxumService;
+
+#[cfg(feature = "axum")]
+mod axum_service {
+    use std::{
+        collections::HashMap,
+        convert::Infallible,
+        future,
+        net::SocketAddr,
+        task::{Context, Poll},
+    };
+
+    use axum::{
+        extract::{
+            ws::{CloseFrame, Message as AxumMessage, WebSocket, WebSocketUpgrade},
+            ConnectInfo, FromRequestParts, Query, Request,
+        },
+        http::StatusCode,
+        response::{IntoResponse, Response},
+    };
+    use futures_util::{future::BoxFuture, Sink, SinkExt, Stream, StreamExt};
+    use warp::{
+        http::{HeaderName, HeaderValue},
+        ws::Message,
+    };
+
+    use super::SUBPROTOCOL;
+    use crate::{client_connected, ConnectionRequest, FoxgloveWebSocket};
+
+    /// The endpoint as a tower service for axum, created by
+    /// [`FoxgloveWebSocket::axum_service`].
+    #[derive(Clone, Debug)]
+    pub struct AxumService {
+        pub(super) server: FoxgloveWebSocket,
+    }
+
+    impl tower_service::Service<Request> for AxumService {
+        type Response = Response;
+        type Error = Infallible;
+        type Future = BoxFuture<'static, Result<Response, Infallible>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: Request) -> Self::Future {
+            let server = self.server.clone();
+            Box::pin(async move { Ok(upgrade(server, request).await) })
+        }
+    }
+
+    /// Authenticates a connecting client and upgrades its connection to a WebSocket.
+    async fn upgrade(server: FoxgloveWebSocket, request: Request) -> Response {
+        let (mut parts, _body) = request.into_parts();
+        let ws = match WebSocketUpgrade::from_request_parts(&mut parts, &()).await {
+            Ok(ws) => ws,
+            Err(rejection) => return rejection.into_response(),
+        };
+
+        // axum is built on newer versions of the http types than warp.
+        let headers = parts
+            .headers
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((
+                    HeaderName::from_bytes(name.as_str().as_bytes()).ok()?,
+                    HeaderValue::from_bytes(value.as_bytes()).ok()?,
+                ))
+            })
+            .collect();
+        let query = Query::<HashMap<String, String>>::try_from_uri(&parts.uri)
+            .map(|Query(query)| query)
+            .unwrap_or_default();
+        let remote_addr = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| *addr)
+            .or_else(|| parts.extensions.get::<SocketAddr>().copied());
+        let request = ConnectionRequest::new(headers, query, remote_addr);
+
+        let identity = match server.authenticate(request).await {
+            Ok(identity) => identity,
+            Err(rejection) => {
+                let status = StatusCode::from_u16(rejection.status().as_u16())
+                    .unwrap_or(StatusCode::FORBIDDEN);
+                return (status, rejection.message().to_owned()).into_response();
+            }
+        };
+        ws.protocols([SUBPROTOCOL])
+            .on_upgrade(move |socket| client_connected(convert_socket(socket), server, identity))
+    }
+
+    /// Translates between the WebSocket messages of axum and warp.
+    fn convert_socket(
+        socket: WebSocket,
+    ) -> impl Stream<Item = Result<Message, axum::Error>> + Sink<Message, Error = axum::Error> {
+        socket
+            .map(|message| message.map(from_axum_message))
+            .with(|message| future::ready(Ok(to_axum_message(message))))
+    }
+
+    fn from_axum_message(message: AxumMessage) -> Message {
+        match message {
+            AxumMessage::Text(text) => Message::text(text.as_str()),
+            AxumMessage::Binary(data) => Message::binary(data),
+            AxumMessage::Ping(data) => Message::ping(data),
+            AxumMessage::Pong(data) => Message::pong(data),
+            AxumMessage::Close(Some(frame)) => {
+                Message::close_with(frame.code, frame.reason.as_str().to_owned())
+            }
+            AxumMessage::Close(None) => Message::close(),
+        }
+    }
+
+    fn to_axum_message(message: Message) -> AxumMessage {
+        if let Some((code, reason)) = message.close_frame() {
+            return AxumMessage::Close(Some(CloseFrame {
+                code,
+                reason: reason.into(),
+            }));
+        }
+        if message.is_close() {
+            return AxumMessage::Close(None);
+        }
+        let (is_text, is_ping, is_pong) = (message.is_text(), message.is_ping(), message.is_pong());
+        let data = message.into_bytes();
+        if is_text {
+            match String::from_utf8(data) {
+                Ok(text) => AxumMessage::Text(text.into()),
+                Err(err) => AxumMessage::Binary(err.into_bytes().into()),
+            }
+        } else if is_ping {
+            AxumMessage::Ping(data.into())
+        } else if is_pong {
+            AxumMessage::Pong(data.into())
+        } else {
+            AxumMessage::Binary(data.into())
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Channels of JSON messages, enabled with the `json` feature.
+
+use std::marker::PhantomData;
+
+use schemars::{
+    generate::{Contract, SchemaSettings},
+    JsonSchema,
+};
+use serde::Serialize;
+
+use crate::{FoxgloveWebSocket, MessageEncoding, PublisherOptions, SchemaDescriptor, TypedChannel};
+
+/// The JSON encoding of messages of type `T`, with the JSON Schema of `T` as generated by
+/// schemars, so that Foxglove knows the types of the fields, e.g. to plot them.
+pub struct Json<T>(PhantomData<T>);
+
+impl<T: Serialize + JsonSchema> MessageEncoding for Json<T> {
+    type Message = T;
+
+    fn encoding() -> String {
+        "json".to_owned()
+    }
+
+    fn schema_name() -> String {
+        T::schema_name().into_owned()
+    }
+
+    fn schema() -> anyhow::Result<SchemaDescriptor> {
+        // Foxglove does not resolve references, so nested types are inlined where possible.
+        let schema = SchemaSettings::draft07()
+            .with(|settings| {
+                settings.inline_subschemas = true;
+                settings.contract = Contract::Serialize;
+            })
+            .into_generator()
+            .into_root_schema_for::<T>();
+        Ok(serde_json::to_string(&schema)?.into())
+    }
+
+    fn schema_encoding() -> Option<String> {
+        Some("jsonschema".to_owned())
+    }
+
+    fn encode(message: &T) -> anyhow::Result<Vec<u8>> {
+        Ok(serde_json::to_vec(message)?)
+    }
+}
+
+impl FoxgloveWebSocket {
+    /// Advertise a new publisher of messages of type `T` serialized to JSON, a shorthand for
+    /// [`FoxgloveWebSocket::create_typed_publisher`] with the [`Json`] encoding.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - Name of the topic of this new channel.
+    /// * `is_latching` - Whether messages sent of this channel are sticky. Each newly connecting
+    ///   client will be sent the last sticky message that was sent on this channel.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// use schemars::JsonSchema;
+    /// use serde::Serialize;
+    ///
+    /// #[derive(JsonSchema, Serialize)]
+    /// struct Battery {
+    ///     voltage: f64,
+    ///     charging: bool,
+    /// }
+    ///
+    /// let server = foxglove_ws::FoxgloveWebSocket::new("robot");
+    /// let channel = server
+    ///     .create_json_publisher::<Battery>("/battery", false)
+    ///     .await?;
+    /// channel
+    ///     .send(0, &Battery { voltage: 12.4, charging: false })
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn create_json_publisher<T: Serialize + JsonSchema>(
+        &self,
+        topic: &str,
+        is_latching: bool,
+    ) -> anyhow::Result<TypedChannel<T>> {
+        self.create_typed_publisher::<Json<T>>(topic, is_latching)
+            .await
+    }
+
+    /// Advertise a new publisher of messages of type `T` serialized to JSON with additional
+    /// options.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - Name of the topic of this new channel.
+    /// * `options` - Options of the channel.
+    pub async fn create_json_publisher_with_options<T: Serialize + JsonSchema>(
+        &self,
+        topic: &str,
+        options: PublisherOptions,
+    ) -> anyhow::Result<TypedChannel<T>> {
+        self.create_typed_publisher_with_options::<Json<T>>(topic, options)
+            .await
+    }
+}
@@ -8,19 +8,14 @@
 //!
 //! # Example
 //!
-//! This is an example with single ROS1 channel/topic with the `std_msgs/String` message type.
+//! This is an example with single ROS1 channel/topic with the `std_msgs/String` message type. The
+//! [`ros1`] module builds the schema from the message definition and serializes the messages.
 //!
 //! ```no_run
-//! use std::{io::Write, time::SystemTime};
+//! use std::time::SystemTime;
 //!
-//! fn build_string_message(data: &str) -> anyhow::Result<Vec<u8>> {
-//!     let mut msg = vec![0; std::mem::size_of::<u32>() + data.len()];
-//!     // ROS 1 message strings are encoded as 4-bytes length and then the byte data.
-//!     let mut w = std::io::Cursor::new(&mut msg);
-//!     w.write(&(data.len() as u32).to_le_bytes())?;
-//!     w.write(data.as_bytes())?;
-//!     Ok(msg)
-//! }
+//! use foxglove_ws::ros1::MessageDefinitions;
+//! use serde_json::json;
 //!
 //! #[tokio::main]
 //! async fn main() -> anyhow::Result<()> {
@@ -29,58 +24,138 @@
 //!         let server = server.clone();
 //!         async move { server.serve(([127, 0, 0, 1], 8765)).await }
 //!     });
+//!     let mut definitions = MessageDefinitions::new();
+//!     definitions.add("std_msgs/String", "string data")?;
 //!     let channel = server
-//!         .publish(
-//!             "/data".to_string(),
-//!             "ros1".to_string(),
-//!             "std_msgs/String".to_string(),
-//!             "string data".to_string(),
-//!             "ros1msg".to_string(),
+//!         .create_publisher(
+//!             "/data",
+//!             "ros1",
+//!             "std_msgs/String",
+//!             definitions.schema("std_msgs/String")?,
+//!             Some("ros1msg"),
 //!             false,
 //!         )
 //!         .await?;
 //!     channel
 //!         .send(
 //!             SystemTime::now().elapsed().unwrap().as_nanos() as u64,
-//!             &build_string_message("Hello!")?,
+//!             &definitions.serialize("std_msgs/String", &json!({ "data": "Hello!" }))?,
 //!         )
 //!         .await?;
 //!     Ok(())
 //! }
 //! ```
 
+mod assets;
+mod auth;
+mod client_publish;
+mod config;
+mod connection_graph;
+mod encoding;
+mod endpoint;
+#[cfg(feature = "json")]
+mod json;
+mod listener;
+mod parameters;
+#[cfg(feature = "protobuf")]
+mod protobuf;
 mod protocol_types;
+mod queue;
+pub mod ros1;
+mod services;
+mod shutdown;
+mod stats;
+mod status;
+mod time;
+#[cfg(feature = "tls")]
+mod tls;
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    future::Future,
     io::{Cursor, Write},
     mem::size_of,
     net::SocketAddr,
+    pin::pin,
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc,
     },
+    time::Duration,
 };
 
 use anyhow::anyhow;
 use base64::{engine::general_purpose, Engine as _};
-use futures_util::{stream::SplitSink, SinkExt, StreamExt, TryFutureExt};
+use futures_util::{
+    future::{self, Either},
+    FutureExt, Sink, SinkExt, Stream, StreamExt,
+};
 use log::debug;
-use tokio::sync::{mpsc, RwLock};
-use tokio_stream::wrappers::ReceiverStream;
+use tokio::sync::{RwLock, Semaphore};
 use uuid::Uuid;
-use warp::{
-    ws::{Message, WebSocket},
-    Filter,
-};
+use warp::ws::Message;
 
+use assets::AssetState;
+use client_publish::ClientPublishState;
+use config::{Capabilities, ServerConfig};
 use protocol_types::*;
+use queue::{ChannelDelivery, ClientQueue, Delivery};
+use services::{ServiceEntry, ServiceState};
+use shutdown::ShutdownState;
+use stats::DeliveryCounters;
+use status::StatusState;
+use time::TimeState;
+
+pub use assets::{
+    AssetProvider, EmbeddedAssetProvider, FileSystemAssetProvider, InMemoryAssetProvider,
+};
+pub use auth::{AuthRejection, AuthorizationPolicy, ConnectionRequest, Identity, Operation};
+pub use client_publish::{ClientChannel, ClientMessageStream, ClientPublishedMessage};
+pub use config::{Capability, FoxgloveWebSocketBuilder};
+pub use connection_graph::ConnectionGraph;
+pub use encoding::{MessageEncoding, TypedChannel};
+#[cfg(feature = "axum")]
+pub use endpoint::AxumService;
+#[cfg(feature = "json")]
+pub use json::Json;
+pub use parameters::{ParameterStore, ParameterValue};
+#[cfg(feature = "protobuf")]
+pub use protobuf::Protobuf;
+pub use queue::BackpressurePolicy;
+pub use services::{AdvertisedService, ServiceRequest, ServiceSchema};
+pub use stats::{ChannelStats, DeliveryStats, ServerStats};
+pub use status::{StatusHandle, StatusLevel};
+pub use time::{Clock, ClockHandle, SystemClock};
+#[cfg(feature = "tls")]
+pub use tls::TlsConfig;
+
+/// Node ID of the server in the connection graph if the server has no name.
+const DEFAULT_SERVER_NODE_ID: &str = "foxglove-ws";
 
 #[derive(Debug)]
 struct Client {
     id: Uuid,
-    tx: mpsc::Sender<Message>,
+    identity: Option<Identity>,
+    tx: Arc<ClientQueue>,
     subscriptions: HashMap<usize, ClientChannelId>,
+    advertisements: HashMap<ClientPublishChannelId, Arc<ClientChannel>>,
+    parameter_subscriptions: HashSet<String>,
+    subscribed_connection_graph: bool,
+    /// Permits for the assets the client fetches at once.
+    asset_fetches: Arc<Semaphore>,
+    /// Permits for the services the client calls at once.
+    service_calls: Arc<Semaphore>,
+}
+
+impl Client {
+    /// Queues a message that is broadcast to all clients. A client whose queue is closed, e.g.
+    /// because it disconnects or the server shuts down, is skipped, so that the other clients
+    /// still get the message.
+    fn broadcast(&self, message: Message) {
+        if let Err(err) = self.tx.send(message) {
+            log::debug!("Skipping broadcast to client {}: {}.", self.id, err);
+        }
+    }
 }
 
 type Clients = RwLock<HashMap<Uuid, Client>>;
@@ -119,11 +194,12 @@ pub struct Channel {
     id: usize,
     topic: String,
     is_latching: bool,
+    delivery: Arc<ChannelDelivery>,
 
     clients: Arc<ClientState>,
     channels: Arc<ChannelState>,
+    connection_graph: ConnectionGraph,
     pinned_message: Arc<RwLock<Option<MessageData>>>,
-    unadvertised: bool,
 }
 
 impl Channel {
@@ -140,15 +216,29 @@ impl Channel {
         };
         for client in self.clients.clients.read().await.values() {
             if let Some(subscription_id) = client.subscriptions.get(&self.id) {
-                log::debug!(
-                    "Send message on {} to client {} ({}).",
-                    self.topic,
-                    client.id,
-                    client.tx.capacity()
-                );
-                client
-                    .tx
-                    .try_send(message_data.build_message(*subscription_id)?)?;
+                log::debug!("Send message on {} to client {}.", self.topic, client.id);
+                let message = message_data.build_message(*subscription_id)?;
+                // A client that does not keep up must not keep the other clients from getting
+                // the message, so failures are handled per client.
+                match client.tx.push(self.id, message, &self.delivery) {
+                    Ok(Delivery::Queued) => {}
+                    Ok(Delivery::Dropped) => log::debug!(
+                        "Dropped message on {} for client {}: queue full.",
+                        self.topic,
+                        client.id
+                    ),
+                    Ok(Delivery::Disconnected) => log::warn!(
+                        "Disconnecting client {}: not keeping up with {}.",
+                        client.id,
+                        self.topic
+                    ),
+                    Err(err) => log::debug!(
+                        "Failed to send message on {} to client {}: {}.",
+                        self.topic,
+                        client.id,
+                        err
+                    ),
+                }
             }
         }
 
@@ -159,65 +249,44 @@ impl Channel {
         Ok(())
     }
 
-    /// Unadvertises this channel to the given client.
-    pub async fn unadvertise(mut self) -> anyhow::Result<()> {
+    /// Unadvertises this channel to all clients.
+    pub async fn unadvertise(self) -> anyhow::Result<()> {
         let message = ServerMessage::Unadvertise {
             channel_ids: vec![self.id],
         };
 
-        for client in self.clients.clients.read().await.values() {
-            if let Some(_subscription_id) = client.subscriptions.get(&self.id) {
-                log::debug!(
-                    "Send message on {} to client {} ({}).",
-                    self.topic,
-                    client.id,
-                    client.tx.capacity()
-                );
-                client
-                    .tx
-                    .send(Message::text(serde_json::to_string(&message)?))
-                    .await?;
-            }
-        }
-
         // remove self from channels
         self.channels.channels.write().await.remove(&self.id);
-        self.unadvertised = true;
 
-        Ok(())
+        let message = Message::text(serde_json::to_string(&message)?);
+        for client in self.clients.clients.read().await.values() {
+            log::debug!("Unadvertise {} to client {}.", self.topic, client.id);
+            client.tx.remove_channel(self.id);
+            client.broadcast(message.clone());
+        }
+
+        self.connection_graph.update().await
     }
 }
 
-impl Drop for Channel {
-    fn drop(&mut self) {
-        if self.unadvertised {
-            let channel_id = self.id;
-            let topic = self.topic.clone();
-            let clients = self.clients.clone();
-            let channels = self.channels.clone();
-            let pinned_message = self.pinned_message.clone();
-            tokio::spawn(async move {
-                if let Err(e) = Channel::unadvertise(Channel {
-                    id: channel_id,
-                    topic,
-                    is_latching: false,
-                    clients,
-                    channels,
-                    pinned_message,
-                    unadvertised: false,
-                })
-                .await
-                {
-                    log::error!("Failed to unadvertise channel: {}", e);
-                }
-            });
-        }
-    }
+/// Options of a channel created with [`FoxgloveWebSocket::create_publisher_with_options`].
+#[derive(Clone, Debug, Default)]
+pub struct PublisherOptions {
+    /// Whether messages sent of this channel are sticky. Each newly connecting client will be
+    /// sent the last sticky message that was sent on this channel.
+    pub is_latching: bool,
+    /// Number of messages of this channel buffered per client. Defaults to the queue size of the
+    /// client. At least one message is buffered.
+    pub queue_size: Option<usize>,
+    /// What happens to messages of this channel for clients that do not keep up. Defaults to the
+    /// policy of the client.
+    pub backpressure: Option<BackpressurePolicy>,
 }
 
 #[derive(Debug)]
 struct ChannelMetadata {
     channel_message: ServerChannelMessage,
+    delivery: Arc<ChannelDelivery>,
     pinned_message: Arc<RwLock<Option<MessageData>>>,
 }
 
@@ -230,35 +299,73 @@ struct ChannelState {
 }
 
 /// The service WebSocket. It tracks the connected clients and takes care of subscriptions.
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug)]
 pub struct FoxgloveWebSocket {
     clients: Arc<ClientState>,
     channels: Arc<ChannelState>,
-    pub parameters: Arc<RwLock<HashMap<String, String>>>,
-    server_name: String,
+    /// Parameters that clients can read, set and subscribe to.
+    pub parameters: ParameterStore,
+    /// Graph of the publishers, subscribers and services connected through this server.
+    pub connection_graph: ConnectionGraph,
+    client_publish: Arc<ClientPublishState>,
+    services: Arc<ServiceState>,
+    time: Arc<TimeState>,
+    statuses: Arc<StatusState>,
+    assets: Arc<AssetState>,
+    shutdown: Arc<ShutdownState>,
+    capabilities: Arc<Capabilities>,
+    config: Arc<ServerConfig>,
 }
 
-async fn initialize_client(
-    user_ws_tx: &mut SplitSink<WebSocket, Message>,
-    channels: &Channels,
-    client_id: &Uuid,
-    parameters: Arc<RwLock<HashMap<String, String>>>,
-    server_name: &str,
-) -> anyhow::Result<()> {
+impl Default for FoxgloveWebSocket {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+async fn initialize_client<E>(
+    user_ws_tx: &mut (impl Sink<Message, Error = E> + Unpin),
+    server: &FoxgloveWebSocket,
+) -> anyhow::Result<()>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let last_time_ns = *server.time.last_time_ns.read().await;
+
+    let has_asset_provider = server.assets.provider.read().await.is_some();
+    let capabilities = server
+        .capabilities
+        .enabled()
+        .into_iter()
+        .filter(|capability| match capability {
+            Capability::Time => last_time_ns.is_some(),
+            Capability::Assets => has_asset_provider,
+            _ => true,
+        })
+        .map(|capability| capability.as_str().to_owned())
+        .collect();
+    let supported_encodings = if server.capabilities.contains(Capability::ClientPublish) {
+        server.config.supported_encodings.clone()
+    } else {
+        vec![]
+    };
+
     user_ws_tx
         .send(Message::text(
             serde_json::to_string(&ServerMessage::ServerInfo {
-                name: server_name.to_owned(),
-                capabilities: vec![String::from("parameters")],
-                supported_encodings: vec![],
-                metadata: HashMap::default(),
-                session_id: client_id.as_hyphenated().to_string(),
+                name: server.config.name.clone(),
+                capabilities,
+                supported_encodings,
+                metadata: server.config.metadata.clone(),
+                session_id: server.config.session_id.clone(),
             })
             .unwrap(),
         ))
         .await?;
 
-    let channel_messages = channels
+    let channel_messages = server
+        .channels
+        .channels
         .read()
         .await
         .values()
@@ -274,37 +381,232 @@ async fn initialize_client(
         ))
         .await?;
 
-    let parameters = parameters
+    let service_messages: Vec<_> = server
+        .services
+        .services
         .read()
         .await
-        .iter()
-        .map(|(name, value)| ParameterValue::new(name, value))
+        .values()
+        .map(|service| service.service_message.clone())
         .collect();
 
-    user_ws_tx
-        .send(Message::text(
-            serde_json::to_string(&ServerMessage::ParameterValues {
-                id: None,
-                parameters,
-            })
-            .unwrap(),
-        ))
-        .await?;
+    if !service_messages.is_empty() {
+        user_ws_tx
+            .send(Message::text(serde_json::to_string(
+                &ServerMessage::AdvertiseServices {
+                    services: service_messages,
+                },
+            )?))
+            .await?;
+    }
+
+    if server.capabilities.contains(Capability::Parameters) {
+        let parameters = server.parameters.parameter_values(&[]).await;
 
+        user_ws_tx
+            .send(Message::text(
+                serde_json::to_string(&ServerMessage::ParameterValues {
+                    id: None,
+                    parameters,
+                })
+                .unwrap(),
+            ))
+            .await?;
+    }
+
+    if let Some(timestamp_ns) = last_time_ns {
+        user_ws_tx
+            .send(time::build_time_message(timestamp_ns)?)
+            .await?;
+    }
+
+    // The statuses are not locked while sending, so a slow client does not hold up others.
+    let statuses: Vec<_> = server
+        .statuses
+        .active
+        .read()
+        .await
+        .values()
+        .map(serde_json::to_string)
+        .collect::<Result<_, _>>()?;
+    for status in statuses {
+        user_ws_tx.send(Message::text(status)).await?;
+    }
+
+    Ok(())
+}
+
+async fn handle_client_binary_msg(
+    tx: &Arc<ClientQueue>,
+    server: &FoxgloveWebSocket,
+    client_id: &Uuid,
+    identity: Option<&Identity>,
+    msg: ClientBinaryMessage<'_>,
+) -> anyhow::Result<()> {
+    let capability = match msg {
+        ClientBinaryMessage::MessageData { .. } => Capability::ClientPublish,
+        ClientBinaryMessage::ServiceCallRequest { .. } => Capability::Services,
+    };
+    if !server.capabilities.contains(capability) {
+        log::warn!(
+            "Ignoring message of client {}: capability {} is disabled.",
+            client_id,
+            capability.as_str()
+        );
+        return Ok(());
+    }
+
+    match msg {
+        ClientBinaryMessage::MessageData { channel_id, data } => {
+            let channel = server
+                .clients
+                .clients
+                .read()
+                .await
+                .get(client_id)
+                .ok_or(anyhow!("Client gone from client map?"))?
+                .advertisements
+                .get(&channel_id)
+                .cloned();
+            // Advertisements the server rejected, e.g. for an unsupported encoding or because
+            // the client is not authorized, end up here, so the data is dropped without
+            // disconnecting the client.
+            let Some(channel) = channel else {
+                log::warn!(
+                    "Dropping data of client {} on unknown channel {}.",
+                    client_id,
+                    channel_id
+                );
+                return Ok(());
+            };
+            server
+                .client_publish
+                .dispatch(ClientPublishedMessage {
+                    client_id: *client_id,
+                    identity: identity.cloned(),
+                    channel,
+                    data: data.to_vec(),
+                })
+                .await;
+        }
+        ClientBinaryMessage::ServiceCallRequest {
+            service_id,
+            call_id,
+            encoding,
+            data,
+        } => {
+            log::debug!(
+                "Client {} calls service {} ({}).",
+                client_id,
+                service_id,
+                call_id
+            );
+            let service_name = server
+                .services
+                .services
+                .read()
+                .await
+                .get(&service_id)
+                .map(|service| service.service_message.name.clone());
+            if let Some(service) = service_name {
+                let operation = Operation::CallService { service: &service };
+                if !authorize(tx, server, client_id, identity, &operation)? {
+                    let failure = ServerMessage::ServiceCallFailure {
+                        service_id,
+                        call_id,
+                        message: format!("Permission denied: {}.", operation),
+                    };
+                    tx.reply(Message::text(serde_json::to_string(&failure)?))?;
+                    return Ok(());
+                }
+            }
+            let calls = server
+                .clients
+                .clients
+                .read()
+                .await
+                .get(client_id)
+                .ok_or(anyhow!("Client gone from client map?"))?
+                .service_calls
+                .clone();
+            services::call_service(
+                tx,
+                &calls,
+                &server.services,
+                service_id,
+                ServiceRequest {
+                    client_id: *client_id,
+                    identity: identity.cloned(),
+                    call_id,
+                    encoding: encoding.to_owned(),
+                    data: data.to_vec(),
+                },
+            )
+            .await?;
+        }
+    }
     Ok(())
 }
 
+/// Checks a client's request against the authorization policy. Denied requests are reported to
+/// the client with an error status.
+fn authorize(
+    tx: &ClientQueue,
+    server: &FoxgloveWebSocket,
+    client_id: &Uuid,
+    identity: Option<&Identity>,
+    operation: &Operation<'_>,
+) -> anyhow::Result<bool> {
+    let Some(policy) = &server.config.authorization_policy else {
+        return Ok(true);
+    };
+    if policy.0.authorize(identity, operation) {
+        return Ok(true);
+    }
+    log::warn!("Denied client {} to {}.", client_id, operation);
+    tx.reply(Message::text(serde_json::to_string(
+        &ServerMessage::Status {
+            level: StatusLevel::Error,
+            message: format!("Permission denied: {}.", operation),
+            id: None,
+        },
+    )?))?;
+    Ok(false)
+}
+
+/// Returns the capability a client message belongs to, if any.
+fn required_capability(msg: &ClientMessage) -> Option<Capability> {
+    match msg {
+        ClientMessage::Subscribe { .. } | ClientMessage::Unsubscribe { .. } => None,
+        ClientMessage::Advertise { .. } | ClientMessage::Unadvertise { .. } => {
+            Some(Capability::ClientPublish)
+        }
+        ClientMessage::GetParameters { .. } | ClientMessage::SetParameters { .. } => {
+            Some(Capability::Parameters)
+        }
+        ClientMessage::SubscribeParameterUpdates { .. }
+        | ClientMessage::UnsubscribeParameterUpdates { .. } => {
+            Some(Capability::ParametersSubscribe)
+        }
+        ClientMessage::SubscribeConnectionGraph | ClientMessage::UnsubscribeConnectionGraph => {
+            Some(Capability::ConnectionGraph)
+        }
+        ClientMessage::FetchAsset { .. } => Some(Capability::Assets),
+    }
+}
+
 async fn handle_client_msg(
-    tx: &mpsc::Sender<Message>,
-    clients: &Arc<ClientState>,
-    channels: &Arc<ChannelState>,
+    tx: &Arc<ClientQueue>,
+    server: &FoxgloveWebSocket,
     client_id: &Uuid,
+    identity: Option<&Identity>,
     ws_msg: &Message,
 ) -> anyhow::Result<()> {
     let msg = if ws_msg.is_text() {
         serde_json::from_str::<ClientMessage>(ws_msg.to_str().unwrap())?
     } else if ws_msg.is_binary() {
-        return Err(anyhow!("Got binary message: unhandled at the moment."));
+        let msg = ClientBinaryMessage::parse(ws_msg.as_bytes())?;
+        return handle_client_binary_msg(tx, server, client_id, identity, msg).await;
     } else if ws_msg.is_close() {
         // Closing the connection is handled in the general loop for the client.
         // Nothing is left to do here.
@@ -316,12 +618,29 @@ async fn handle_client_msg(
         ));
     };
 
-    let mut clients = clients.clients.write().await;
+    if let Some(capability) = required_capability(&msg) {
+        if !server.capabilities.contains(capability) {
+            log::warn!(
+                "Ignoring message of client {}: capability {} is disabled.",
+                client_id,
+                capability.as_str()
+            );
+            return Ok(());
+        }
+    }
 
-    let channels = channels.channels.read().await;
+    let changes_connection_graph = matches!(
+        msg,
+        ClientMessage::Subscribe { .. }
+            | ClientMessage::Unsubscribe { .. }
+            | ClientMessage::Advertise { .. }
+            | ClientMessage::Unadvertise { .. }
+    );
 
     match msg {
         ClientMessage::Subscribe { ref subscriptions } => {
+            let mut clients = server.clients.clients.write().await;
+            let channels = server.channels.channels.read().await;
             let client = clients
                 .get_mut(client_id)
                 .ok_or(anyhow!("Client gone from client map?"))?;
@@ -334,12 +653,30 @@ async fn handle_client_msg(
                 );
 
                 if let Some(channel_metadata) = channels.get(channel_id) {
-                    client.subscriptions.insert(*channel_id, *id);
+                    let topic = &channel_metadata.channel_message.topic;
+                    if !authorize(
+                        tx,
+                        server,
+                        client_id,
+                        identity,
+                        &Operation::Subscribe { topic },
+                    )? {
+                        continue;
+                    }
+                    // Messages queued for an earlier subscription of the channel carry the old
+                    // subscription ID.
+                    if client.subscriptions.insert(*channel_id, *id).is_some() {
+                        tx.remove_channel(*channel_id);
+                    }
                     if let Some(message_data) =
                         channel_metadata.pinned_message.read().await.as_ref()
                     {
                         log::debug!("Sending latched: client {}.", client_id);
-                        tx.send(message_data.build_message(*id)?).await?;
+                        tx.push(
+                            *channel_id,
+                            message_data.build_message(*id)?,
+                            &channel_metadata.delivery,
+                        )?;
                     }
                 }
             }
@@ -347,84 +684,317 @@ async fn handle_client_msg(
         ClientMessage::Unsubscribe {
             ref subscription_ids,
         } => {
+            let mut clients = server.clients.clients.write().await;
             let client = clients
                 .get_mut(client_id)
                 .ok_or(anyhow!("Client gone from client map?"))?;
             log::debug!("Client {} unsubscribes {:?}.", client_id, subscription_ids);
+            client.subscriptions.retain(|channel_id, subscription_id| {
+                let keep = !subscription_ids.contains(subscription_id);
+                if !keep {
+                    tx.remove_channel(*channel_id);
+                }
+                keep
+            });
+        }
+        ClientMessage::Advertise { channels } => {
+            let mut clients = server.clients.clients.write().await;
+            let client = clients
+                .get_mut(client_id)
+                .ok_or(anyhow!("Client gone from client map?"))?;
+            for channel in channels {
+                if !server
+                    .config
+                    .supported_encodings
+                    .contains(&channel.encoding)
+                {
+                    log::warn!(
+                        "Client {} advertised {} with unsupported encoding {}.",
+                        client_id,
+                        channel.topic,
+                        channel.encoding
+                    );
+                    tx.reply(Message::text(serde_json::to_string(
+                        &ServerMessage::Status {
+                            level: StatusLevel::Error,
+                            message: format!(
+                                "Unsupported encoding {} for {}, supported are: {}.",
+                                channel.encoding,
+                                channel.topic,
+                                server.config.supported_encodings.join(", ")
+                            ),
+                            id: None,
+                        },
+                    )?))?;
+                    continue;
+                }
+                let operation = Operation::Advertise {
+                    topic: &channel.topic,
+                };
+                if !authorize(tx, server, client_id, identity, &operation)? {
+                    continue;
+                }
+                log::debug!(
+                    "Client {} advertised {} on {}.",
+                    client_id,
+                    channel.topic,
+                    channel.id
+                );
+                client
+                    .advertisements
+                    .insert(channel.id, Arc::new(channel.into()));
+            }
+        }
+        ClientMessage::Unadvertise { channel_ids } => {
+            let mut clients = server.clients.clients.write().await;
+            let client = clients
+                .get_mut(client_id)
+                .ok_or(anyhow!("Client gone from client map?"))?;
+            log::debug!("Client {} unadvertises {:?}.", client_id, channel_ids);
             client
-                .subscriptions
-                .retain(|_, subscription_id| !subscription_ids.contains(subscription_id));
+                .advertisements
+                .retain(|channel_id, _| !channel_ids.contains(channel_id));
         }
         ClientMessage::GetParameters {
             parameter_names,
-            id: _,
+            id,
         } => {
             debug!(
                 "Client {} requested parameters: {:?}",
                 client_id, parameter_names
             );
+            let parameters = server.parameters.parameter_values(&parameter_names).await;
+            tx.reply(Message::text(serde_json::to_string(
+                &ServerMessage::ParameterValues { parameters, id },
+            )?))?;
         }
-        ClientMessage::SetParameters { parameters, id: _ } => {
+        ClientMessage::SetParameters { parameters, id } => {
             debug!("Client {} set parameters: {:?}", client_id, parameters);
+            // Parameters are set all at once or not at all.
+            for parameter in &parameters {
+                let operation = Operation::SetParameter {
+                    name: &parameter.name,
+                };
+                if !authorize(tx, server, client_id, identity, &operation)? {
+                    return Ok(());
+                }
+            }
+            let mut values = Vec::with_capacity(parameters.len());
+            for parameter in &parameters {
+                match parameter.decode_value() {
+                    Ok(value) => values.push((parameter.name.clone(), value)),
+                    Err(err) => {
+                        log::warn!(
+                            "Client {} set invalid value of parameter {}: {}",
+                            client_id,
+                            parameter.name,
+                            err
+                        );
+                        tx.reply(Message::text(serde_json::to_string(
+                            &ServerMessage::Status {
+                                level: StatusLevel::Error,
+                                message: format!(
+                                    "Invalid value of parameter {}: {}",
+                                    parameter.name, err
+                                ),
+                                id: None,
+                            },
+                        )?))?;
+                        return Ok(());
+                    }
+                }
+            }
+            server.parameters.update_from_client(values).await?;
+            // The updated values are only sent back if the client asked for them by setting an ID.
+            // Deleted parameters are sent back without a value.
+            if id.is_some() {
+                let names: Vec<_> = parameters
+                    .into_iter()
+                    .map(|parameter| parameter.name)
+                    .collect();
+                let parameters = server.parameters.parameter_values_or_unset(&names).await;
+                tx.reply(Message::text(serde_json::to_string(
+                    &ServerMessage::ParameterValues { parameters, id },
+                )?))?;
+            }
+        }
+        ClientMessage::SubscribeParameterUpdates { parameter_names } => {
+            let mut clients = server.clients.clients.write().await;
+            let client = clients
+                .get_mut(client_id)
+                .ok_or(anyhow!("Client gone from client map?"))?;
+            log::debug!(
+                "Client {} subscribes parameters {:?}.",
+                client_id,
+                parameter_names
+            );
+            client.parameter_subscriptions.extend(parameter_names);
         }
+        ClientMessage::UnsubscribeParameterUpdates { parameter_names } => {
+            let mut clients = server.clients.clients.write().await;
+            let client = clients
+                .get_mut(client_id)
+                .ok_or(anyhow!("Client gone from client map?"))?;
+            log::debug!(
+                "Client {} unsubscribes parameters {:?}.",
+                client_id,
+                parameter_names
+            );
+            client
+                .parameter_subscriptions
+                .retain(|name| !parameter_names.contains(name));
+        }
+        ClientMessage::SubscribeConnectionGraph => {
+            log::debug!("Client {} subscribes the connection graph.", client_id);
+            server.connection_graph.subscribe(client_id).await?;
+        }
+        ClientMessage::UnsubscribeConnectionGraph => {
+            let mut clients = server.clients.clients.write().await;
+            let client = clients
+                .get_mut(client_id)
+                .ok_or(anyhow!("Client gone from client map?"))?;
+            log::debug!("Client {} unsubscribes the connection graph.", client_id);
+            client.subscribed_connection_graph = false;
+        }
+        ClientMessage::FetchAsset { uri, request_id } => {
+            log::debug!("Client {} fetches asset {}.", client_id, uri);
+            let operation = Operation::FetchAsset { uri: &uri };
+            if !authorize(tx, server, client_id, identity, &operation)? {
+                assets::reject_fetch_asset(
+                    tx,
+                    request_id,
+                    &format!("Permission denied: {}.", operation),
+                )?;
+                return Ok(());
+            }
+            let fetches = server
+                .clients
+                .clients
+                .read()
+                .await
+                .get(client_id)
+                .ok_or(anyhow!("Client gone from client map?"))?
+                .asset_fetches
+                .clone();
+            let provider = server.assets.provider.read().await.clone();
+            assets::fetch_asset(tx, &fetches, provider, request_id, uri)?;
+        }
+    }
+
+    if changes_connection_graph {
+        server.connection_graph.update().await?;
     }
     Ok(())
 }
 
-async fn client_connected(
-    ws: WebSocket,
-    clients: Arc<ClientState>,
-    channels: Arc<ChannelState>,
-    parameters: Arc<RwLock<HashMap<String, String>>>,
-    server_name: String,
-) {
+/// Runs the connection of a client until it disconnects. All ways of accepting clients end up
+/// here, `ws` only has to be a stream and sink of WebSocket messages.
+async fn client_connected<S, E>(ws: S, server: FoxgloveWebSocket, identity: Option<Identity>)
+where
+    S: Stream<Item = Result<Message, E>> + Sink<Message, Error = E> + Send + 'static,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    // Keep the server from returning out of its shutdown before this connection finished.
+    let _connection = server.shutdown.track_connection();
+
     // Split the socket into a sender and receive of messages.
-    let (mut user_ws_tx, mut user_ws_rx) = ws.split();
+    let (mut user_ws_tx, user_ws_rx) = ws.split();
 
     let client_id = Uuid::new_v4();
-    log::info!("Client {} connected.", client_id);
-
-    // Send server info.
-    if let Err(err) = initialize_client(
-        &mut user_ws_tx,
-        &channels.channels,
-        &client_id,
-        parameters,
-        &server_name,
-    )
-    .await
-    {
-        log::error!("Failed to initialize client: {}.", err);
-        return;
+    match &identity {
+        Some(identity) => log::info!("Client {} connected as {}.", client_id, identity.name),
+        None => log::info!("Client {} connected.", client_id),
     }
 
-    // TODO(mkiefel): Add per channel queue sizes.
-    let (tx, rx) = mpsc::channel(10);
-    let mut rx = ReceiverStream::new(rx);
+    let tx = Arc::new(ClientQueue::new(
+        server.config.client_queue_size,
+        server.config.backpressure,
+    ));
 
-    // Setup the sender queue task.
-    tokio::task::spawn(async move {
-        while let Some(message) = rx.next().await {
-            user_ws_tx
-                .send(message)
-                .unwrap_or_else(|e| {
-                    log::error!("Failed websocket send: {}.", e);
-                })
-                .await;
-        }
-    });
-
-    // Save the sender in our list of connected users.
-    clients.clients.write().await.insert(
+    // Save the sender in our list of connected users. This happens before the client is sent the
+    // current state, so that changes made meanwhile, e.g. a new status, are queued for it instead
+    // of being missed. Such changes may reach the client twice.
+    server.clients.clients.write().await.insert(
         client_id,
         Client {
             id: client_id,
+            identity: identity.clone(),
             tx: tx.clone(),
             subscriptions: HashMap::new(),
+            advertisements: HashMap::new(),
+            parameter_subscriptions: HashSet::new(),
+            subscribed_connection_graph: false,
+            asset_fetches: Arc::new(Semaphore::new(assets::MAX_CONCURRENT_FETCHES)),
+            service_calls: Arc::new(Semaphore::new(services::MAX_CONCURRENT_CALLS)),
         },
     );
+    // The server may have started shutting down before the client was added to the list.
+    if server.shutdown.is_aborted() {
+        tx.close();
+    } else if server.shutdown.is_shutting_down() {
+        shutdown::finish_client(&tx);
+    }
+
+    // Send server info. Give up on clients that do not receive it once the server shuts down.
+    let initialized = {
+        let initialized = pin!(initialize_client(&mut user_ws_tx, &server));
+        let aborted = pin!(server.shutdown.aborted());
+        match future::select(initialized, aborted).await {
+            Either::Left((result, _)) => Some(result),
+            Either::Right(_) => None,
+        }
+    };
+    match initialized {
+        Some(Ok(())) => {}
+        Some(Err(err)) => {
+            log::error!("Failed to initialize client: {}.", err);
+            tx.close();
+            server.clients.clients.write().await.remove(&client_id);
+            return;
+        }
+        None => {
+            log::info!("Client {} closed during shutdown.", client_id);
+            tx.close();
+            server.clients.clients.write().await.remove(&client_id);
+            return;
+        }
+    }
+
+    // Setup the sender queue task.
+    let writer = tokio::task::spawn({
+        let tx = tx.clone();
+        async move {
+            while let Some((message, channel)) = tx.pop().await {
+                // Counts the message as dropped unless it is written, also when this task is
+                // aborted while writing it.
+                let in_flight = tx.in_flight(&message, channel);
+                match user_ws_tx.send(message).await {
+                    Ok(()) => in_flight.sent(),
+                    Err(e) => {
+                        log::error!("Failed websocket send: {}.", e);
+                        // The connection is broken, so everything still queued is dropped when
+                        // the queue is closed below.
+                        break;
+                    }
+                }
+            }
+            if let Err(err) = user_ws_tx.close().await {
+                log::debug!("Failed to close websocket: {}.", err);
+            }
+            // Nothing is sent to the client anymore, so stop receiving as well.
+            tx.close();
+        }
+    });
 
-    while let Some(result) = user_ws_rx.next().await {
+    // Stop receiving once the queue is closed, e.g. because the client was too slow.
+    let mut user_ws_rx = pin!(user_ws_rx.take_until(tx.closed()));
+    loop {
+        // A client that does not read the replies to its requests is not read from either, so
+        // that the replies do not pile up.
+        tx.ready_for_request().await;
+        let Some(result) = user_ws_rx.next().await else {
+            break;
+        };
         let ws_msg = match result {
             Ok(ws_msg) => ws_msg,
             Err(err) => {
@@ -432,62 +1002,271 @@ async fn client_connected(
                 break;
             }
         };
-        if let Err(err) = handle_client_msg(&tx, &clients, &channels, &client_id, &ws_msg).await {
+        if let Err(err) =
+            handle_client_msg(&tx, &server, &client_id, identity.as_ref(), &ws_msg).await
+        {
             log::error!("Failed handling client message: {}.", err);
             break;
         }
     }
 
     log::info!("Client {} closed.", client_id);
-    clients.clients.write().await.remove(&client_id);
+    tx.close();
+    // The writer may still be stuck sending to a client that stopped reading.
+    writer.abort();
+    let _ = writer.await;
+    server.clients.clients.write().await.remove(&client_id);
+    if let Err(err) = server.connection_graph.update().await {
+        log::error!("Failed to update connection graph: {}.", err);
+    }
 }
 
 impl FoxgloveWebSocket {
     /// Creates a new Foxglove WebSocket service.
     pub fn new(server_name: &str) -> Self {
-        let server_name = server_name.to_owned();
+        Self::builder().name(server_name).build()
+    }
+
+    /// Returns a builder to configure a new Foxglove WebSocket service.
+    pub fn builder() -> FoxgloveWebSocketBuilder {
+        FoxgloveWebSocketBuilder::new()
+    }
+
+    pub(crate) fn with_config(config: ServerConfig) -> Self {
+        let clients = Arc::<ClientState>::default();
+        let channels = Arc::<ChannelState>::default();
+        let services = Arc::<ServiceState>::default();
+        let capabilities = Arc::new(Capabilities::new(&config));
+        let server_node_id = if config.name.is_empty() {
+            DEFAULT_SERVER_NODE_ID
+        } else {
+            &config.name
+        };
         Self {
-            server_name,
-            ..Default::default()
+            parameters: ParameterStore::new(clients.clone()),
+            connection_graph: ConnectionGraph::new(
+                server_node_id,
+                clients.clone(),
+                channels.clone(),
+                services.clone(),
+                capabilities.clone(),
+            ),
+            clients,
+            channels,
+            client_publish: Arc::new(ClientPublishState::new(config.client_message_queue_size)),
+            services,
+            time: Arc::default(),
+            statuses: Arc::default(),
+            assets: Arc::default(),
+            shutdown: Arc::default(),
+            capabilities,
+            config: Arc::new(config),
         }
     }
 
     /// Serves connecting clients.
     ///
+    /// Panics if the server cannot listen on `addr`. Use [`FoxgloveWebSocket::try_bind`] to handle
+    /// this error instead.
+    ///
     /// # Arguments
     ///
     /// `addr` -- Address to listen on.
     pub async fn serve(&self, addr: impl Into<SocketAddr>) {
-        let clients = self.clients.clone();
-        let clients = warp::any().map(move || clients.clone());
-        let channels = self.channels.clone();
-        let channels = warp::any().map(move || channels.clone());
-        let parameters = self.parameters.clone();
-        let parameters = warp::any().map(move || parameters.clone());
-        let server_name = self.server_name.to_owned();
-        let server_name = warp::any().map(move || server_name.clone());
-        let foxglove_ws = warp::path::end().and(
-            warp::ws()
-                .and(clients)
-                .and(channels)
-                .and(parameters)
-                .and(server_name)
-                .map(
-                    |ws: warp::ws::Ws, clients, channels, parameters, server_name: String| {
-                        ws.on_upgrade(move |socket| {
-                            client_connected(socket, clients, channels, parameters, server_name)
-                        })
+        warp::serve(self.filter()).run(addr).await;
+    }
+
+    /// Listens on `addr` and returns the address actually listened on together with the future
+    /// serving connecting clients. The future never finishes.
+    ///
+    /// Binding to port 0 picks a free port, which is then part of the returned address. Has to be
+    /// called from within a Tokio runtime.
+    ///
+    /// # Arguments
+    ///
+    /// `addr` -- Address to listen on.
+    pub fn try_bind(
+        &self,
+        addr: impl Into<SocketAddr>,
+    ) -> anyhow::Result<(SocketAddr, impl Future<Output = ()> + Send + 'static)> {
+        self.serve_with_shutdown(addr, future::pending())
+    }
+
+    /// Listens on `addr` and returns the address actually listened on together with the future
+    /// serving connecting clients until `signal` finishes.
+    ///
+    /// On shutdown the server stops accepting connections, shows a final status to the connected
+    /// clients and closes their connections once their queued control messages are written.
+    /// Queued channel messages are dropped. Clients that do not disconnect within a few seconds
+    /// are disconnected forcefully. The returned future finishes once all client connections
+    /// finished, after which the server may be served again. Has to be called from within a Tokio
+    /// runtime.
+    ///
+    /// # Arguments
+    ///
+    /// `addr` -- Address to listen on.
+    /// `signal` -- Future that triggers the shutdown when it finishes.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # async fn example(server: foxglove_ws::FoxgloveWebSocket) -> anyhow::Result<()> {
+    /// let (addr, serving) = server.serve_with_shutdown(([127, 0, 0, 1], 0), async {
+    ///     tokio::signal::ctrl_c().await.ok();
+    /// })?;
+    /// println!("Listening on {}.", addr);
+    /// serving.await;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn serve_with_shutdown(
+        &self,
+        addr: impl Into<SocketAddr>,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> anyhow::Result<(SocketAddr, impl Future<Output = ()> + Send + 'static)> {
+        let (addr, serving) =
+            warp::serve(self.filter()).try_bind_with_graceful_shutdown(addr.into(), signal)?;
+        let server = self.clone();
+        Ok((addr, async move {
+            serving.await;
+            server.shutdown_clients().await;
+        }))
+    }
+
+    /// Disconnects all clients and waits for their connections to finish.
+    async fn shutdown_clients(&self) {
+        log::info!("Shutting down, disconnecting clients.");
+        self.shutdown.begin();
+        for client in self.clients.clients.read().await.values() {
+            shutdown::finish_client(&client.tx);
+        }
+        let finished = tokio::time::timeout(
+            shutdown::SHUTDOWN_TIMEOUT,
+            self.shutdown.connections_finished(),
+        )
+        .await;
+        if finished.is_err() {
+            log::warn!("Clients did not disconnect in time, closing their connections.");
+            self.shutdown.abort();
+            for client in self.clients.clients.read().await.values() {
+                client.tx.close();
+            }
+            self.shutdown.connections_finished().await;
+        }
+        self.shutdown.reset();
+    }
+
+    /// Subscribes to messages that clients publish on the given topic.
+    ///
+    /// Clients first advertise a channel for a topic and then publish messages on it, e.g. from
+    /// Foxglove's Publish or Teleop panels. Messages are handed out together with the publishing
+    /// client's ID and the advertised channel, which holds the encoding and schema of the data.
+    /// Messages are dropped for this subscriber if it does not keep up with consuming the stream.
+    ///
+    /// Enables the `clientPublish` capability unless it is disabled, so clients that connect
+    /// afterwards may publish.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - Name of the topic to receive client messages for.
+    pub async fn subscribe_client_messages(&self, topic: &str) -> ClientMessageStream {
+        if let Err(err) = self.capabilities.enable(Capability::ClientPublish) {
+            log::warn!("Clients cannot publish to {}: {}", topic, err);
+        }
+        self.client_publish.subscribe(topic).await
+    }
+
+    /// Returns a snapshot of the number of messages and bytes sent, dropped and queued per client
+    /// and per channel.
+    ///
+    /// Messages count as sent once they are written to the client's WebSocket. Dropped messages
+    /// include messages discarded because a client did not keep up, unsubscribed or
+    /// disconnected.
+    ///
+    /// The messages a client is sent right after connecting, i.e. the server info and the
+    /// current channels, services, parameters, time and statuses, are not counted. They are
+    /// written before the client's queue takes over.
+    pub async fn stats(&self) -> ServerStats {
+        let clients = self
+            .clients
+            .clients
+            .read()
+            .await
+            .iter()
+            .map(|(client_id, client)| (*client_id, client.tx.counters.snapshot()))
+            .collect();
+        let channels = self
+            .channels
+            .channels
+            .read()
+            .await
+            .iter()
+            .map(|(channel_id, metadata)| {
+                (
+                    *channel_id,
+                    ChannelStats {
+                        topic: metadata.channel_message.topic.clone(),
+                        delivery: metadata.delivery.counters.snapshot(),
                     },
                 )
-                .map(|reply| {
-                    warp::reply::with_header(
-                        reply,
-                        "Sec-WebSocket-Protocol",
-                        "foxglove.websocket.v1",
-                    )
-                }),
-        );
-        warp::serve(foxglove_ws).run(addr).await;
+            })
+            .collect();
+        ServerStats { clients, channels }
+    }
+
+    /// Returns the identity that the authenticator assigned to a connected client. Returns
+    /// `None` for unknown clients and if no authenticator is set.
+    pub async fn client_identity(&self, client_id: &Uuid) -> Option<Identity> {
+        self.clients
+            .clients
+            .read()
+            .await
+            .get(client_id)?
+            .identity
+            .clone()
+    }
+
+    /// Sets what happens to messages for a client that does not keep up. Channels with their own
+    /// policy keep using that one.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - ID of the client.
+    /// * `backpressure` - Policy for the client.
+    pub async fn set_client_backpressure(
+        &self,
+        client_id: &Uuid,
+        backpressure: BackpressurePolicy,
+    ) -> anyhow::Result<()> {
+        let clients = self.clients.clients.read().await;
+        let client = clients
+            .get(client_id)
+            .ok_or(anyhow!("Unknown client {}.", client_id))?;
+        *client.tx.backpressure.lock().unwrap() = backpressure;
+        Ok(())
+    }
+
+    /// Sets the number of messages buffered for each subscription of a client. Channels with
+    /// their own queue size keep using that one.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - ID of the client.
+    /// * `queue_size` - Number of messages, at least one message is buffered.
+    pub async fn set_client_queue_size(
+        &self,
+        client_id: &Uuid,
+        queue_size: usize,
+    ) -> anyhow::Result<()> {
+        let clients = self.clients.clients.read().await;
+        let client = clients
+            .get(client_id)
+            .ok_or(anyhow!("Unknown client {}.", client_id))?;
+        client
+            .tx
+            .queue_size
+            .store(queue_size.max(1), Ordering::Relaxed);
+        Ok(())
     }
 
     /// Advertise a new publisher.
@@ -504,7 +1283,7 @@ impl FoxgloveWebSocket {
     /// * `schema` - Schema describing the message format.
     /// * `scheme_encoding` - Optional type of encoding used for schema encoding. May be used if the schema encoding can't be uniquely deduced from the message encoding.
     /// * `is_latching` - Whether messages sent of this channel are sticky. Each newly connecting
-    ///    client will be message the last sticky message that was sent on this channel.
+    ///   client will be message the last sticky message that was sent on this channel.
     pub async fn create_publisher<S: Into<SchemaDescriptor>>(
         &self,
         topic: &str,
@@ -513,6 +1292,39 @@ impl FoxgloveWebSocket {
         schema: S,
         schema_encoding: Option<&str>,
         is_latching: bool,
+    ) -> anyhow::Result<Channel> {
+        self.create_publisher_with_options(
+            topic,
+            encoding,
+            schema_name,
+            schema,
+            schema_encoding,
+            PublisherOptions {
+                is_latching,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// Advertise a new publisher with additional options.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - Name of the topic of this new channel.
+    /// * `encoding` - Channel message encoding.
+    /// * `schema_name` - Name of the schema.
+    /// * `schema` - Schema describing the message format.
+    /// * `scheme_encoding` - Optional type of encoding used for schema encoding.
+    /// * `options` - Options of the channel.
+    pub async fn create_publisher_with_options<S: Into<SchemaDescriptor>>(
+        &self,
+        topic: &str,
+        encoding: &str,
+        schema_name: &str,
+        schema: S,
+        schema_encoding: Option<&str>,
+        options: PublisherOptions,
     ) -> anyhow::Result<Channel> {
         let channel_id = self
             .channels
@@ -522,11 +1334,16 @@ impl FoxgloveWebSocket {
         let channel = Channel {
             id: channel_id,
             topic: topic.to_owned(),
-            is_latching,
+            is_latching: options.is_latching,
+            delivery: Arc::new(ChannelDelivery {
+                queue_size: options.queue_size.map(|queue_size| queue_size.max(1)),
+                backpressure: options.backpressure,
+                counters: DeliveryCounters::default(),
+            }),
             clients: self.clients.clone(),
             channels: self.channels.clone(),
+            connection_graph: self.connection_graph.clone(),
             pinned_message: Arc::default(),
-            unadvertised: false,
         };
         let channel_message = ServerChannelMessage {
             id: channel_id,
@@ -538,29 +1355,327 @@ impl FoxgloveWebSocket {
         };
 
         // Advertise the newly created channel.
+        let message = Message::text(serde_json::to_string(&ServerMessage::Advertise {
+            channels: vec![channel_message.clone()],
+        })?);
         for client in self.clients.clients.read().await.values() {
-            client
-                .tx
-                .send(Message::text(
-                    serde_json::to_string(&ServerMessage::Advertise {
-                        channels: vec![channel_message.clone()],
-                    })
-                    .unwrap(),
-                ))
-                .await?;
+            client.broadcast(message.clone());
         }
 
         self.channels.channels.write().await.insert(
             channel_id,
             ChannelMetadata {
                 channel_message,
+                delivery: channel.delivery.clone(),
                 pinned_message: channel.pinned_message.clone(),
             },
         );
+        self.connection_graph.update().await?;
 
         Ok(channel)
     }
 
+    /// Broadcasts the current server time to all clients.
+    ///
+    /// Once time has been broadcast, the server announces the `time` capability to newly
+    /// connecting clients and Foxglove follows the server's clock instead of the wall clock. This
+    /// makes it possible to visualize simulations that run faster or slower than real time.
+    /// Fails if the time capability is disabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp_ns` - Current server time in nanoseconds.
+    pub async fn broadcast_time(&self, timestamp_ns: u64) -> anyhow::Result<()> {
+        self.capabilities.enable(Capability::Time)?;
+        *self.time.last_time_ns.write().await = Some(timestamp_ns);
+        let message = time::build_time_message(timestamp_ns)?;
+        for client in self.clients.clients.read().await.values() {
+            // An outdated time is of no use, so the update is skipped for clients that are not
+            // keeping up.
+            if let Err(err) = client.tx.try_send(message.clone()) {
+                log::debug!("Skipping time update for client {}: {}.", client.id, err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts broadcasting the time of `clock` to all clients in regular intervals.
+    ///
+    /// The clock keeps running until the returned handle is dropped. Fails if `period` is zero.
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - Source of the time, e.g. [`SystemClock`] or a closure returning the simulation
+    ///   time in nanoseconds.
+    /// * `period` - Time between two broadcasts.
+    pub fn start_clock(&self, clock: impl Clock, period: Duration) -> anyhow::Result<ClockHandle> {
+        if period.is_zero() {
+            return Err(anyhow!("Period of the clock must not be zero."));
+        }
+        let server = self.clone();
+        let task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            loop {
+                interval.tick().await;
+                if let Err(err) = server.broadcast_time(clock.now_ns()).await {
+                    log::error!("Failed to broadcast time: {}.", err);
+                }
+            }
+        });
+        Ok(ClockHandle { task, period })
+    }
+
+    /// Shows a status message to all connected clients.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - Severity of the status.
+    /// * `message` - Text of the status.
+    /// * `id` - Optional ID that can later be used to remove the status again with
+    ///   [`FoxgloveWebSocket::remove_status`].
+    pub async fn publish_status(
+        &self,
+        level: StatusLevel,
+        message: &str,
+        id: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let message = ServerMessage::Status {
+            level,
+            message: message.to_owned(),
+            id: id.map(|id| id.to_owned()),
+        };
+        status::send_status_message(&message, None, &self.clients).await
+    }
+
+    /// Shows a status message to a single client.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - ID of the client to show the status to.
+    /// * `level` - Severity of the status.
+    /// * `message` - Text of the status.
+    /// * `id` - Optional ID that can later be used to remove the status again.
+    pub async fn publish_status_to(
+        &self,
+        client_id: &Uuid,
+        level: StatusLevel,
+        message: &str,
+        id: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let message = ServerMessage::Status {
+            level,
+            message: message.to_owned(),
+            id: id.map(|id| id.to_owned()),
+        };
+        status::send_status_message(&message, Some(client_id), &self.clients).await
+    }
+
+    /// Removes status messages with the given IDs from all clients.
+    pub async fn remove_status(&self, status_ids: &[&str]) -> anyhow::Result<()> {
+        let message = ServerMessage::RemoveStatus {
+            status_ids: status_ids.iter().map(|id| (*id).to_owned()).collect(),
+        };
+        status::send_status_message(&message, None, &self.clients).await
+    }
+
+    /// Shows a status message to all clients until the returned handle is cleared or dropped.
+    ///
+    /// Unlike [`FoxgloveWebSocket::publish_status`], the status is also shown to clients that
+    /// connect while the handle is alive.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - Severity of the status.
+    /// * `message` - Text of the status.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # async fn example(server: foxglove_ws::FoxgloveWebSocket) -> anyhow::Result<()> {
+    /// use foxglove_ws::StatusLevel;
+    ///
+    /// let status = server
+    ///     .create_status(StatusLevel::Warning, "Lidar disconnected")
+    ///     .await?;
+    /// // ...
+    /// status.clear().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn create_status(
+        &self,
+        level: StatusLevel,
+        message: &str,
+    ) -> anyhow::Result<StatusHandle> {
+        let handle = self.status_handle(None);
+        let message = ServerMessage::Status {
+            level,
+            message: message.to_owned(),
+            id: Some(handle.id.clone()),
+        };
+        self.statuses
+            .active
+            .write()
+            .await
+            .insert(handle.id.clone(), message.clone());
+        status::send_status_message(&message, None, &self.clients).await?;
+        Ok(handle)
+    }
+
+    /// Shows a status message to a single client until the returned handle is cleared or
+    /// dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `client_id` - ID of the client to show the status to.
+    /// * `level` - Severity of the status.
+    /// * `message` - Text of the status.
+    pub async fn create_client_status(
+        &self,
+        client_id: &Uuid,
+        level: StatusLevel,
+        message: &str,
+    ) -> anyhow::Result<StatusHandle> {
+        let mut handle = self.status_handle(Some(*client_id));
+        let message = ServerMessage::Status {
+            level,
+            message: message.to_owned(),
+            id: Some(handle.id.clone()),
+        };
+        if let Err(err) =
+            status::send_status_message(&message, Some(client_id), &self.clients).await
+        {
+            // There is nothing to remove if the status never made it to the client.
+            handle.cleared = true;
+            return Err(err);
+        }
+        Ok(handle)
+    }
+
+    fn status_handle(&self, client_id: Option<Uuid>) -> StatusHandle {
+        StatusHandle {
+            id: Uuid::new_v4().as_hyphenated().to_string(),
+            client_id,
+            clients: self.clients.clone(),
+            statuses: self.statuses.clone(),
+            cleared: false,
+        }
+    }
+
+    /// Sets the provider of the assets that clients can fetch, e.g. the meshes referenced by a
+    /// URDF. Enables the `assets` capability unless it is disabled, so clients that connect
+    /// afterwards are told that the server supports assets.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # async fn example(server: foxglove_ws::FoxgloveWebSocket) {
+    /// use foxglove_ws::FileSystemAssetProvider;
+    ///
+    /// server
+    ///     .set_asset_provider(
+    ///         FileSystemAssetProvider::new("/opt/robot/share")
+    ///             .with_package("robot", "/home/user/robot_description"),
+    ///     )
+    ///     .await;
+    /// # }
+    /// ```
+    pub async fn set_asset_provider(&self, provider: impl AssetProvider) {
+        *self.assets.provider.write().await = Some(Arc::new(provider));
+        if let Err(err) = self.capabilities.enable(Capability::Assets) {
+            log::warn!("Clients cannot fetch assets: {}", err);
+        }
+    }
+
+    /// Advertise a new service that clients can call.
+    ///
+    /// Each call is handled in its own task by `handler`. The returned data is sent back to the
+    /// calling client as the response, encoded with the same encoding as the request. If the
+    /// handler fails, the client is sent the error message instead.
+    ///
+    /// Enables the `services` capability, so clients that connect afterwards can call services.
+    /// Fails if the capability is disabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the service.
+    /// * `service_type` - Type of the service, e.g. `std_srvs/Empty`.
+    /// * `request` - Description of the request message.
+    /// * `response` - Description of the response message.
+    /// * `handler` - Async function called for each service call.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # async fn example(server: foxglove_ws::FoxgloveWebSocket) -> anyhow::Result<()> {
+    /// use foxglove_ws::ServiceSchema;
+    ///
+    /// let _service = server
+    ///     .advertise_service(
+    ///         "/reset_odometry",
+    ///         "std_srvs/Empty",
+    ///         ServiceSchema::new("json", "Empty", "{}", "jsonschema"),
+    ///         ServiceSchema::new("json", "Empty", "{}", "jsonschema"),
+    ///         |_request| async { Ok(b"{}".to_vec()) },
+    ///     )
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn advertise_service<F, Fut>(
+        &self,
+        name: &str,
+        service_type: &str,
+        request: ServiceSchema,
+        response: ServiceSchema,
+        handler: F,
+    ) -> anyhow::Result<AdvertisedService>
+    where
+        F: Fn(ServiceRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<Vec<u8>>> + Send + 'static,
+    {
+        self.capabilities.enable(Capability::Services)?;
+        let service_id = self
+            .services
+            .next_service_id
+            .fetch_add(1, Ordering::Relaxed);
+        log::debug!("Advertising new service {}: {}.", name, service_id);
+        let service_message = ServerServiceMessage {
+            id: service_id,
+            name: name.to_owned(),
+            service_type: service_type.to_owned(),
+            request: request.into(),
+            response: response.into(),
+        };
+        let service = AdvertisedService {
+            id: service_id,
+            name: name.to_owned(),
+            clients: self.clients.clone(),
+            services: self.services.clone(),
+            connection_graph: self.connection_graph.clone(),
+            unadvertised: false,
+        };
+
+        self.services.services.write().await.insert(
+            service_id,
+            ServiceEntry {
+                service_message: service_message.clone(),
+                handler: Arc::new(move |request| handler(request).boxed()),
+            },
+        );
+
+        let message = Message::text(serde_json::to_string(&ServerMessage::AdvertiseServices {
+            services: vec![service_message],
+        })?);
+        for client in self.clients.clients.read().await.values() {
+            client.broadcast(message.clone());
+        }
+        self.connection_graph.update().await?;
+
+        Ok(service)
+    }
+
     /// Advertise a new publisher.
     ///
     /// There are several different message encoding schemes that are supported by Foxglove.
@@ -575,7 +1690,7 @@ impl FoxgloveWebSocket {
     /// * `schema` - Schema describing the message format.
     /// * `scheme_encoding` - Encoding of this channel's schema.
     /// * `is_latching` - Whether messages sent of this channel are sticky. Each newly connecting
-    ///    client will be message the last sticky message that was sent on this channel.
+    ///   client will be message the last sticky message that was sent on this channel.
     #[deprecated(note = "Please use `create_publisher` instead")]
     pub async fn publish(
         &self,
@@ -0,0 +1,252 @@
+//! Serving clients on listeners and streams set up by the application, e.g. with systemd socket
+//! activation or in-memory streams in tests.
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    io,
+    net::{SocketAddr, TcpListener as StdTcpListener},
+    pin::pin,
+    time::Duration,
+};
+
+use futures_util::{
+    future::{self, Either},
+    Sink, Stream,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+};
+use warp::{
+    hyper::{
+        self,
+        server::conn::Http,
+        service::{service_fn, Service},
+        Body, Request, Response,
+    },
+    ws::Message,
+};
+
+use crate::{client_connected, FoxgloveWebSocket, Identity};
+
+/// Time to wait before accepting connections again after accepting failed, e.g. because the
+/// process ran out of file descriptors.
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+
+/// Time a connection has for the handshake, e.g. establishing TLS, before it is closed. Keeps
+/// clients that connect and then stall from holding on to their connection forever.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl FoxgloveWebSocket {
+    /// Serves clients connecting to an already bound listener. The future never finishes.
+    ///
+    /// # Arguments
+    ///
+    /// `listener` -- Listener to accept connections from.
+    pub async fn serve_listener(&self, listener: TcpListener) {
+        self.serve_listener_with_shutdown(listener, future::pending())
+            .await;
+    }
+
+    /// Serves clients connecting to an already bound listener until `signal` finishes.
+    ///
+    /// Shuts down like [`FoxgloveWebSocket::serve_with_shutdown`].
+    ///
+    /// # Arguments
+    ///
+    /// `listener` -- Listener to accept connections from.
+    /// `signal` -- Future that triggers the shutdown when it finishes.
+    pub async fn serve_listener_with_shutdown(
+        &self,
+        listener: TcpListener,
+        signal: impl Future<Output = ()>,
+    ) {
+        self.accept_connections(listener, signal, future::ok).await;
+        self.shutdown_clients().await;
+    }
+
+    /// Returns the future serving clients connecting to an already bound standard library
+    /// listener, e.g. one passed in by systemd socket activation. The future never finishes.
+    ///
+    /// Has to be called from within a Tokio runtime.
+    ///
+    /// # Arguments
+    ///
+    /// `listener` -- Listener to accept connections from.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # async fn example(server: foxglove_ws::FoxgloveWebSocket) -> anyhow::Result<()> {
+    /// use std::{net::TcpListener, os::fd::FromRawFd};
+    ///
+    /// // The first socket passed in by systemd.
+    /// let listener = unsafe { TcpListener::from_raw_fd(3) };
+    /// server.serve_std_listener(listener)?.await;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn serve_std_listener(
+        &self,
+        listener: StdTcpListener,
+    ) -> anyhow::Result<impl Future<Output = ()> + Send + 'static> {
+        self.serve_std_listener_with_shutdown(listener, future::pending())
+    }
+
+    /// Returns the future serving clients connecting to an already bound standard library
+    /// listener until `signal` finishes.
+    ///
+    /// Shuts down like [`FoxgloveWebSocket::serve_with_shutdown`]. Has to be called from within
+    /// a Tokio runtime.
+    ///
+    /// # Arguments
+    ///
+    /// `listener` -- Listener to accept connections from.
+    /// `signal` -- Future that triggers the shutdown when it finishes.
+    pub fn serve_std_listener_with_shutdown(
+        &self,
+        listener: StdTcpListener,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> anyhow::Result<impl Future<Output = ()> + Send + 'static> {
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        let server = self.clone();
+        Ok(async move {
+            server.serve_listener_with_shutdown(listener, signal).await;
+        })
+    }
+
+    /// Serves a single client speaking HTTP on `stream`, e.g. one end of
+    /// [`tokio::io::duplex`]. The client has to upgrade the connection to a WebSocket like on a
+    /// TCP connection, on any path.
+    ///
+    /// The future finishes once the connection is closed or upgraded. The upgraded connection
+    /// is served in the background.
+    ///
+    /// # Arguments
+    ///
+    /// `stream` -- Connection to the client.
+    /// `remote_addr` -- Address of the client passed to the authenticator, if known.
+    pub async fn serve_stream<T>(
+        &self,
+        stream: T,
+        remote_addr: Option<SocketAddr>,
+    ) -> anyhow::Result<()>
+    where
+        T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        serve_connection(self.service(), stream, remote_addr).await?;
+        Ok(())
+    }
+
+    /// Serves a client on a WebSocket connection the application already upgraded itself, e.g.
+    /// with its own warp route. The future finishes once the client disconnected.
+    ///
+    /// The authenticator is not run for these connections, the application passes the identity
+    /// of the client instead. WebSockets of other libraries can be served by converting their
+    /// messages to and from warp's.
+    ///
+    /// # Arguments
+    ///
+    /// `ws` -- WebSocket connection to the client.
+    /// `identity` -- Identity of the client, `None` if it is not known.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # async fn example(server: foxglove_ws::FoxgloveWebSocket) {
+    /// use foxglove_ws::Identity;
+    /// use warp::{ws::Ws, Filter};
+    ///
+    /// let route = warp::path("foxglove")
+    ///     .and(warp::ws())
+    ///     .and(warp::header::<String>("x-user"))
+    ///     .map(move |ws: Ws, user: String| {
+    ///         let server = server.clone();
+    ///         ws.on_upgrade(move |socket| async move {
+    ///             server.serve_websocket(socket, Some(Identity::new(&user))).await;
+    ///         })
+    ///     });
+    /// warp::serve(route).run(([0, 0, 0, 0], 8080)).await;
+    /// # }
+    /// ```
+    pub async fn serve_websocket<S, E>(&self, ws: S, identity: Option<Identity>)
+    where
+        S: Stream<Item = Result<Message, E>> + Sink<Message, Error = E> + Send + 'static,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        client_connected(ws, self.clone(), identity).await;
+    }
+
+    /// Accepts connections from `listener` until `signal` finishes and serves each of them once
+    /// `handshake` set it up, e.g. established TLS.
+    pub(crate) async fn accept_connections<H, F, T>(
+        &self,
+        listener: TcpListener,
+        signal: impl Future<Output = ()>,
+        handshake: H,
+    ) where
+        H: Fn(TcpStream) -> F,
+        F: Future<Output = io::Result<T>> + Send + 'static,
+        T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let service = self.service();
+        let mut signal = pin!(signal);
+        loop {
+            let accepted = pin!(listener.accept());
+            let (stream, remote_addr) = match future::select(accepted, signal.as_mut()).await {
+                Either::Left((Ok(accepted), _)) => accepted,
+                Either::Left((Err(err), _)) => {
+                    log::error!("Failed to accept connection: {}.", err);
+                    tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                    continue;
+                }
+                Either::Right(_) => return,
+            };
+            let handshake = handshake(stream);
+            let service = service.clone();
+            tokio::spawn(async move {
+                let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(err)) => {
+                        log::debug!("Handshake with {} failed: {}.", remote_addr, err);
+                        return;
+                    }
+                    Err(_) => {
+                        log::debug!("Handshake with {} timed out.", remote_addr);
+                        return;
+                    }
+                };
+                if let Err(err) = serve_connection(service, stream, Some(remote_addr)).await {
+                    log::debug!("Connection with {} failed: {}.", remote_addr, err);
+                }
+            });
+        }
+    }
+}
+
+/// Serves the HTTP requests on a connection with `service`, including the WebSocket upgrade.
+async fn serve_connection<S, T>(
+    mut service: S,
+    stream: T,
+    remote_addr: Option<SocketAddr>,
+) -> hyper::Result<()>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible> + Send + 'static,
+    S::Future: Send + 'static,
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    // warp only knows the address of connections it accepted itself.
+    let service = service_fn(move |mut request: Request<Body>| {
+        if let Some(remote_addr) = remote_addr {
+            request.extensions_mut().insert(remote_addr);
+        }
+        service.call(request)
+    });
+    Http::new()
+        .http1_only(true)
+        .serve_connection(stream, service)
+        .with_upgrades()
+        .await
+}
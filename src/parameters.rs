@@ -0,0 +1,581 @@
+//! Server side parameters (`parameters` and `parametersSubscribe` capabilities).
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use anyhow::anyhow;
+use base64::{engine::general_purpose, Engine as _};
+use serde_json::Value;
+use tokio::sync::RwLock;
+use warp::ws::Message;
+
+use crate::{protocol_types::*, ClientState};
+
+/// Value of a parameter.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParameterValue {
+    /// Integral number.
+    Integer(i64),
+    /// Floating point number. Foxglove keeps treating it as floating point even if the value
+    /// happens to be integral.
+    Float64(f64),
+    /// Boolean value.
+    Bool(bool),
+    /// String value.
+    String(String),
+    /// Binary data, transferred base64 encoded.
+    ByteArray(Vec<u8>),
+    /// List of values.
+    Array(Vec<ParameterValue>),
+    /// Nested dictionary of values.
+    Dict(BTreeMap<String, ParameterValue>),
+}
+
+impl ParameterValue {
+    /// Returns the value as floating point number if it is a number.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            ParameterValue::Integer(value) => Some(*value as f64),
+            ParameterValue::Float64(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as integer if it is an integral number.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            ParameterValue::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value if it is a boolean.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            ParameterValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value if it is a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            ParameterValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value if it is a byte array.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            ParameterValue::ByteArray(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value if it is an array.
+    pub fn as_array(&self) -> Option<&[ParameterValue]> {
+        match self {
+            ParameterValue::Array(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value if it is a dictionary.
+    pub fn as_dict(&self) -> Option<&BTreeMap<String, ParameterValue>> {
+        match self {
+            ParameterValue::Dict(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            ParameterValue::Integer(value) => Value::from(*value),
+            ParameterValue::Float64(value) => Value::from(*value),
+            ParameterValue::Bool(value) => Value::from(*value),
+            ParameterValue::String(value) => Value::from(value.as_str()),
+            ParameterValue::ByteArray(value) => {
+                Value::from(general_purpose::STANDARD.encode(value))
+            }
+            ParameterValue::Array(values) => values.iter().map(Self::to_json).collect(),
+            ParameterValue::Dict(values) => Value::Object(
+                values
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.to_json()))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn from_json(value: Value) -> anyhow::Result<Self> {
+        Ok(match value {
+            Value::Null => return Err(anyhow!("Parameter values can't be null.")),
+            Value::Bool(value) => ParameterValue::Bool(value),
+            Value::Number(value) => match value.as_i64() {
+                Some(value) => ParameterValue::Integer(value),
+                None => ParameterValue::Float64(
+                    value
+                        .as_f64()
+                        .ok_or(anyhow!("Unsupported number {}.", value))?,
+                ),
+            },
+            Value::String(value) => ParameterValue::String(value),
+            Value::Array(values) => ParameterValue::Array(
+                values
+                    .into_iter()
+                    .map(Self::from_json)
+                    .collect::<anyhow::Result<_>>()?,
+            ),
+            Value::Object(values) => ParameterValue::Dict(
+                values
+                    .into_iter()
+                    .map(|(name, value)| Ok((name, Self::from_json(value)?)))
+                    .collect::<anyhow::Result<_>>()?,
+            ),
+        })
+    }
+
+    /// Fails if the value is or holds a floating point number that is not finite. JSON cannot
+    /// represent those.
+    fn check_finite(&self) -> anyhow::Result<()> {
+        match self {
+            ParameterValue::Float64(value) if !value.is_finite() => {
+                Err(anyhow!("Value {} is not finite.", value))
+            }
+            ParameterValue::Array(values) => values.iter().try_for_each(Self::check_finite),
+            ParameterValue::Dict(values) => values.values().try_for_each(Self::check_finite),
+            _ => Ok(()),
+        }
+    }
+
+    /// Turns integers into floating point numbers where `current` holds floating point numbers.
+    /// Clients may send integral values of floating point parameters without their type.
+    fn keep_float(&mut self, current: &ParameterValue) {
+        match (self, current) {
+            (value @ ParameterValue::Integer(_), ParameterValue::Float64(_)) => {
+                *value = ParameterValue::Float64(value.as_f64().unwrap_or_default());
+            }
+            (ParameterValue::Array(values), current)
+                if current.parameter_type() == Some(ParameterType::Float64Array) =>
+            {
+                for value in values {
+                    if let ParameterValue::Integer(integer) = value {
+                        *value = ParameterValue::Float64(*integer as f64);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn parameter_type(&self) -> Option<ParameterType> {
+        match self {
+            ParameterValue::Float64(_) => Some(ParameterType::Float64),
+            ParameterValue::ByteArray(_) => Some(ParameterType::ByteArray),
+            ParameterValue::Array(values)
+                if !values.is_empty()
+                    && values
+                        .iter()
+                        .all(|value| matches!(value, ParameterValue::Float64(_))) =>
+            {
+                Some(ParameterType::Float64Array)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Parameter {
+    pub(crate) fn new(name: &str, value: Option<&ParameterValue>) -> Self {
+        Self {
+            name: name.to_owned(),
+            value: value.map(ParameterValue::to_json),
+            parameter_type: value.and_then(ParameterValue::parameter_type),
+        }
+    }
+
+    /// Decodes the value of this parameter. Missing values decode to `None`.
+    pub(crate) fn decode_value(&self) -> anyhow::Result<Option<ParameterValue>> {
+        let Some(value) = self.value.clone() else {
+            return Ok(None);
+        };
+        let value = match (self.parameter_type, value) {
+            (Some(ParameterType::ByteArray), Value::String(value)) => {
+                ParameterValue::ByteArray(general_purpose::STANDARD.decode(value)?)
+            }
+            (Some(ParameterType::Float64), Value::Number(value)) => ParameterValue::Float64(
+                value
+                    .as_f64()
+                    .ok_or(anyhow!("Unsupported number {}.", value))?,
+            ),
+            (Some(ParameterType::Float64Array), Value::Array(values)) => ParameterValue::Array(
+                values
+                    .iter()
+                    .map(|value| {
+                        value
+                            .as_f64()
+                            .map(ParameterValue::Float64)
+                            .ok_or(anyhow!("Expected number, got {}.", value))
+                    })
+                    .collect::<anyhow::Result<_>>()?,
+            ),
+            (None, value) => ParameterValue::from_json(value)?,
+            (Some(parameter_type), value) => {
+                return Err(anyhow!(
+                    "Parameter {} has type {:?} but value {}.",
+                    self.name,
+                    parameter_type,
+                    value
+                ))
+            }
+        };
+        Ok(Some(value))
+    }
+}
+
+macro_rules! impl_from_for_parameter_value {
+    ($type:ty, $variant:ident) => {
+        impl From<$type> for ParameterValue {
+            fn from(value: $type) -> Self {
+                ParameterValue::$variant(value.into())
+            }
+        }
+    };
+}
+
+impl_from_for_parameter_value!(i64, Integer);
+impl_from_for_parameter_value!(i32, Integer);
+impl_from_for_parameter_value!(u32, Integer);
+impl_from_for_parameter_value!(f64, Float64);
+impl_from_for_parameter_value!(f32, Float64);
+impl_from_for_parameter_value!(bool, Bool);
+impl_from_for_parameter_value!(String, String);
+impl_from_for_parameter_value!(&str, String);
+impl_from_for_parameter_value!(Vec<u8>, ByteArray);
+impl_from_for_parameter_value!(&[u8], ByteArray);
+impl_from_for_parameter_value!(Vec<ParameterValue>, Array);
+impl_from_for_parameter_value!(BTreeMap<String, ParameterValue>, Dict);
+
+impl From<Vec<f64>> for ParameterValue {
+    fn from(values: Vec<f64>) -> Self {
+        ParameterValue::Array(values.into_iter().map(ParameterValue::Float64).collect())
+    }
+}
+
+macro_rules! impl_try_from_parameter_value {
+    ($type:ty, $getter:ident) => {
+        impl TryFrom<ParameterValue> for $type {
+            type Error = anyhow::Error;
+
+            fn try_from(value: ParameterValue) -> anyhow::Result<Self> {
+                value
+                    .$getter()
+                    .map(Into::into)
+                    .ok_or_else(|| anyhow!("Expected {}, got {:?}.", stringify!($type), value))
+            }
+        }
+    };
+}
+
+impl_try_from_parameter_value!(i64, as_i64);
+impl_try_from_parameter_value!(f64, as_f64);
+impl_try_from_parameter_value!(bool, as_bool);
+impl_try_from_parameter_value!(String, as_str);
+impl_try_from_parameter_value!(Vec<u8>, as_bytes);
+
+impl TryFrom<ParameterValue> for Vec<f64> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: ParameterValue) -> anyhow::Result<Self> {
+        value
+            .as_array()
+            .and_then(|values| values.iter().map(ParameterValue::as_f64).collect())
+            .ok_or_else(|| anyhow!("Expected array of numbers, got {:?}.", value))
+    }
+}
+
+/// Parameters of the server that clients can read, set and subscribe to.
+///
+/// Every change made through the store is pushed to all clients that subscribed to the changed
+/// parameters.
+#[derive(Clone, Debug)]
+pub struct ParameterStore {
+    values: Arc<RwLock<HashMap<String, ParameterValue>>>,
+    clients: Arc<ClientState>,
+}
+
+impl ParameterStore {
+    pub(crate) fn new(clients: Arc<ClientState>) -> Self {
+        Self {
+            values: Arc::default(),
+            clients,
+        }
+    }
+
+    /// Returns the current value of a parameter.
+    pub async fn get(&self, name: &str) -> Option<ParameterValue> {
+        self.values.read().await.get(name).cloned()
+    }
+
+    /// Returns the current value of a parameter converted to `T`.
+    ///
+    /// Fails if the parameter is set but holds a value of a different type.
+    pub async fn get_as<T>(&self, name: &str) -> anyhow::Result<Option<T>>
+    where
+        T: TryFrom<ParameterValue, Error = anyhow::Error>,
+    {
+        self.get(name).await.map(T::try_from).transpose()
+    }
+
+    /// Returns a snapshot of all parameters.
+    pub async fn get_all(&self) -> HashMap<String, ParameterValue> {
+        self.values.read().await.clone()
+    }
+
+    /// Sets a parameter and notifies subscribed clients.
+    ///
+    /// Fails if the value is or holds a floating point number that is not finite, e.g. NaN,
+    /// because JSON cannot represent it.
+    pub async fn set(&self, name: &str, value: impl Into<ParameterValue>) -> anyhow::Result<()> {
+        self.set_many([(name.to_owned(), value.into())]).await
+    }
+
+    /// Sets several parameters at once and notifies subscribed clients with a single update.
+    ///
+    /// Fails without setting any parameter if a value is not finite.
+    pub async fn set_many<I>(&self, parameters: I) -> anyhow::Result<()>
+    where
+        I: IntoIterator<Item = (String, ParameterValue)>,
+    {
+        self.update(
+            parameters
+                .into_iter()
+                .map(|(name, value)| (name, Some(value)))
+                .collect(),
+        )
+        .await
+    }
+
+    /// Removes a parameter and notifies subscribed clients.
+    pub async fn remove(&self, name: &str) -> anyhow::Result<()> {
+        self.update(vec![(name.to_owned(), None)]).await
+    }
+
+    /// Sets or removes (for `None` values) parameters and notifies subscribed clients. Fails
+    /// without changing any parameter if a value is not finite.
+    pub(crate) async fn update(
+        &self,
+        parameters: Vec<(String, Option<ParameterValue>)>,
+    ) -> anyhow::Result<()> {
+        self.apply(parameters, false).await
+    }
+
+    /// Like [`ParameterStore::update`] for the values a client sets. Parameters holding floating
+    /// point numbers keep doing so when the client sends integral numbers.
+    pub(crate) async fn update_from_client(
+        &self,
+        parameters: Vec<(String, Option<ParameterValue>)>,
+    ) -> anyhow::Result<()> {
+        self.apply(parameters, true).await
+    }
+
+    async fn apply(
+        &self,
+        mut parameters: Vec<(String, Option<ParameterValue>)>,
+        keep_float: bool,
+    ) -> anyhow::Result<()> {
+        for (name, value) in &parameters {
+            if let Some(value) = value {
+                value
+                    .check_finite()
+                    .map_err(|err| anyhow!("Invalid value of parameter {}: {}", name, err))?;
+            }
+        }
+        {
+            let mut values = self.values.write().await;
+            for (name, value) in &mut parameters {
+                if let (true, Some(value), Some(current)) =
+                    (keep_float, value.as_mut(), values.get(name))
+                {
+                    value.keep_float(current);
+                }
+                match value {
+                    Some(value) => values.insert(name.clone(), value.clone()),
+                    None => values.remove(name),
+                };
+            }
+        }
+        self.notify_subscribers(
+            parameters
+                .iter()
+                .map(|(name, value)| Parameter::new(name, value.as_ref()))
+                .collect(),
+        )
+        .await
+    }
+
+    /// Returns the requested parameters. An empty list of names selects all parameters.
+    pub(crate) async fn parameter_values(&self, names: &[String]) -> Vec<Parameter> {
+        let values = self.values.read().await;
+        if names.is_empty() {
+            values
+                .iter()
+                .map(|(name, value)| Parameter::new(name, Some(value)))
+                .collect()
+        } else {
+            names
+                .iter()
+                .filter_map(|name| {
+                    values
+                        .get(name)
+                        .map(|value| Parameter::new(name, Some(value)))
+                })
+                .collect()
+        }
+    }
+
+    /// Returns the named parameters, including the ones that are not set. Those come without
+    /// a value.
+    pub(crate) async fn parameter_values_or_unset(&self, names: &[String]) -> Vec<Parameter> {
+        let values = self.values.read().await;
+        names
+            .iter()
+            .map(|name| Parameter::new(name, values.get(name)))
+            .collect()
+    }
+
+    async fn notify_subscribers(&self, parameters: Vec<Parameter>) -> anyhow::Result<()> {
+        for client in self.clients.clients.read().await.values() {
+            let parameters: Vec<_> = parameters
+                .iter()
+                .filter(|parameter| client.parameter_subscriptions.contains(&parameter.name))
+                .cloned()
+                .collect();
+            if parameters.is_empty() {
+                continue;
+            }
+            client.broadcast(Message::text(serde_json::to_string(
+                &ServerMessage::ParameterValues {
+                    parameters,
+                    id: None,
+                },
+            )?));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn parameter(value: Value, parameter_type: Option<ParameterType>) -> Parameter {
+        Parameter {
+            name: "p".to_owned(),
+            value: Some(value),
+            parameter_type,
+        }
+    }
+
+    /// Sends `value` to a client and decodes it again.
+    fn round_trip(value: ParameterValue) -> (Value, ParameterValue) {
+        let encoded = serde_json::to_value(Parameter::new("p", Some(&value))).unwrap();
+        let decoded = serde_json::from_value::<Parameter>(encoded.clone())
+            .unwrap()
+            .decode_value()
+            .unwrap()
+            .unwrap();
+        (encoded, decoded)
+    }
+
+    #[test]
+    fn encodes_byte_arrays_as_base64() {
+        let value = ParameterValue::ByteArray(b"foxglove".to_vec());
+        let (encoded, decoded) = round_trip(value.clone());
+        assert_eq!(
+            encoded,
+            json!({"name": "p", "value": "Zm94Z2xvdmU=", "type": "byte_array"})
+        );
+        assert_eq!(decoded, value);
+
+        // Without a type, the string stays a string.
+        let value = parameter(json!("Zm94Z2xvdmU="), None).decode_value();
+        assert_eq!(
+            value.unwrap(),
+            Some(ParameterValue::String("Zm94Z2xvdmU=".to_owned()))
+        );
+        let value = parameter(json!("not base64!"), Some(ParameterType::ByteArray)).decode_value();
+        assert!(value.is_err());
+    }
+
+    #[test]
+    fn encodes_float64_arrays_with_their_type() {
+        let value = ParameterValue::from(vec![1.0, 2.5]);
+        let (encoded, decoded) = round_trip(value.clone());
+        assert_eq!(
+            encoded,
+            json!({"name": "p", "value": [1.0, 2.5], "type": "float64_array"})
+        );
+        assert_eq!(decoded, value);
+
+        // Integral values stay floating point numbers with the type.
+        let value = parameter(json!([1, 2]), Some(ParameterType::Float64Array)).decode_value();
+        assert_eq!(value.unwrap(), Some(ParameterValue::from(vec![1.0, 2.0])));
+        let value = parameter(json!([1, "2"]), Some(ParameterType::Float64Array)).decode_value();
+        assert!(value.is_err());
+    }
+
+    #[tokio::test]
+    async fn keeps_floating_point_parameters_set_by_clients() {
+        let store = ParameterStore::new(Arc::default());
+        store
+            .set_many([
+                ("float".to_owned(), 0.5.into()),
+                ("floats".to_owned(), vec![0.5].into()),
+                ("integer".to_owned(), 1.into()),
+            ])
+            .await
+            .unwrap();
+
+        let values = ["float", "floats", "integer"].map(|name| {
+            let value = match name {
+                "floats" => json!([1, 2]),
+                _ => json!(2),
+            };
+            let value = parameter(value, None).decode_value().unwrap();
+            (name.to_owned(), value)
+        });
+        store.update_from_client(values.to_vec()).await.unwrap();
+        assert_eq!(store.get("float").await, Some(ParameterValue::Float64(2.0)));
+        assert_eq!(
+            store.get("floats").await,
+            Some(ParameterValue::from(vec![1.0, 2.0]))
+        );
+        assert_eq!(store.get("integer").await, Some(ParameterValue::Integer(2)));
+
+        // The application decides on the type itself.
+        store.set("float", 3).await.unwrap();
+        assert_eq!(store.get("float").await, Some(ParameterValue::Integer(3)));
+    }
+
+    #[tokio::test]
+    async fn rejects_non_finite_values() {
+        let store = ParameterStore::new(Arc::default());
+        store.set("p", 1.0).await.unwrap();
+        assert!(store.set("p", f64::NAN).await.is_err());
+        assert!(store.set("p", vec![1.0, f64::INFINITY]).await.is_err());
+        assert!(store
+            .set_many([
+                ("p".to_owned(), 2.0.into()),
+                ("q".to_owned(), f64::NEG_INFINITY.into()),
+            ])
+            .await
+            .is_err());
+        assert_eq!(store.get_all().await, [("p".to_owned(), 1.0.into())].into());
+    }
+}
@@ -0,0 +1,111 @@
+//! Channels of protobuf messages, enabled with the `protobuf` feature.
+
+use std::{collections::HashSet, marker::PhantomData};
+
+use prost::Message as _;
+use prost_reflect::{prost_types::FileDescriptorSet, FileDescriptor, ReflectMessage};
+
+use crate::{FoxgloveWebSocket, MessageEncoding, PublisherOptions, SchemaDescriptor, TypedChannel};
+
+/// The protobuf encoding of messages of type `T`.
+///
+/// The schema is the descriptor set of the file defining `T` and of all files it depends on.
+/// Types generated by prost implement [`ReflectMessage`] with `prost-reflect-build` or the
+/// `derive` feature of prost-reflect.
+pub struct Protobuf<T>(PhantomData<T>);
+
+impl<T: ReflectMessage + Default> MessageEncoding for Protobuf<T> {
+    type Message = T;
+
+    fn encoding() -> String {
+        "protobuf".to_owned()
+    }
+
+    fn schema_name() -> String {
+        T::default().descriptor().full_name().to_owned()
+    }
+
+    fn schema() -> anyhow::Result<SchemaDescriptor> {
+        let descriptor = T::default().descriptor();
+        Ok(file_descriptor_set(descriptor.parent_file())
+            .encode_to_vec()
+            .into())
+    }
+
+    fn schema_encoding() -> Option<String> {
+        Some("protobuf".to_owned())
+    }
+
+    fn encode(message: &T) -> anyhow::Result<Vec<u8>> {
+        Ok(message.encode_to_vec())
+    }
+}
+
+impl FoxgloveWebSocket {
+    /// Advertise a new publisher of protobuf messages of type `T`, a shorthand for
+    /// [`FoxgloveWebSocket::create_typed_publisher`] with the [`Protobuf`] encoding.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - Name of the topic of this new channel.
+    /// * `is_latching` - Whether messages sent of this channel are sticky. Each newly connecting
+    ///   client will be sent the last sticky message that was sent on this channel.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// use prost_reflect::prost_types::Timestamp;
+    ///
+    /// let server = foxglove_ws::FoxgloveWebSocket::new("robot");
+    /// let channel = server
+    ///     .create_protobuf_publisher::<Timestamp>("/stamp", false)
+    ///     .await?;
+    /// channel
+    ///     .send(0, &Timestamp { seconds: 1, nanos: 0 })
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn create_protobuf_publisher<T: ReflectMessage + Default>(
+        &self,
+        topic: &str,
+        is_latching: bool,
+    ) -> anyhow::Result<TypedChannel<T>> {
+        self.create_typed_publisher::<Protobuf<T>>(topic, is_latching)
+            .await
+    }
+
+    /// Advertise a new publisher of protobuf messages of type `T` with additional options.
+    ///
+    /// # Arguments
+    ///
+    /// * `topic` - Name of the topic of this new channel.
+    /// * `options` - Options of the channel.
+    pub async fn create_protobuf_publisher_with_options<T: ReflectMessage + Default>(
+        &self,
+        topic: &str,
+        options: PublisherOptions,
+    ) -> anyhow::Result<TypedChannel<T>> {
+        self.create_typed_publisher_with_options::<Protobuf<T>>(topic, options)
+            .await
+    }
+}
+
+/// Collects `file` and the files it depends on, dependencies first.
+fn file_descriptor_set(file: FileDescriptor) -> FileDescriptorSet {
+    fn collect(file: FileDescriptor, seen: &mut HashSet<String>, set: &mut FileDescriptorSet) {
+        if !seen.insert(file.name().to_owned()) {
+            return;
+        }
+        for dependency in file.dependencies() {
+            collect(dependency, seen, set);
+        }
+        set.file.push(file.file_descriptor_proto().clone());
+    }
+
+    let mut set = FileDescriptorSet::default();
+    collect(file, &mut HashSet::new(), &mut set);
+    set
+}
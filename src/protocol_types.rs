@@ -1,8 +1,11 @@
 //! Types for the foxglove websocket protocol messages.
 //! Spec for the protocol can be found here: <https://github.com/foxglove/ws-protocol/blob/main/docs/spec.md>
 
+use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{collections::HashMap, mem::size_of};
+
+use crate::StatusLevel;
 
 #[derive(Clone, Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -16,6 +19,49 @@ pub(crate) struct ServerChannelMessage {
     pub(crate) schema_encoding: Option<String>,
 }
 
+pub(crate) type ServiceId = u32;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ServiceMessageSchema {
+    pub(crate) encoding: String,
+    pub(crate) schema_name: String,
+    pub(crate) schema_encoding: String,
+    pub(crate) schema: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ServerServiceMessage {
+    pub(crate) id: ServiceId,
+    pub(crate) name: String,
+    #[serde(rename = "type")]
+    pub(crate) service_type: String,
+    pub(crate) request: ServiceMessageSchema,
+    pub(crate) response: ServiceMessageSchema,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PublishedTopicMessage {
+    pub(crate) name: String,
+    pub(crate) publisher_ids: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SubscribedTopicMessage {
+    pub(crate) name: String,
+    pub(crate) subscriber_ids: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AdvertisedServiceMessage {
+    pub(crate) name: String,
+    pub(crate) provider_ids: Vec<String>,
+}
+
 #[derive(Clone, Debug, Serialize)]
 #[serde(tag = "op", rename_all = "camelCase")]
 pub(crate) enum ServerMessage {
@@ -33,14 +79,54 @@ pub(crate) enum ServerMessage {
     Unadvertise { channel_ids: Vec<usize> },
     #[serde(rename_all = "camelCase")]
     ParameterValues {
-        parameters: Vec<ParameterValue>,
+        parameters: Vec<Parameter>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    AdvertiseServices { services: Vec<ServerServiceMessage> },
+    #[serde(rename_all = "camelCase")]
+    UnadvertiseServices { service_ids: Vec<ServiceId> },
+    #[serde(rename_all = "camelCase")]
+    ServiceCallFailure {
+        service_id: ServiceId,
+        call_id: u32,
+        message: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Status {
+        level: StatusLevel,
+        message: String,
         #[serde(skip_serializing_if = "Option::is_none")]
         id: Option<String>,
     },
+    #[serde(rename_all = "camelCase")]
+    RemoveStatus { status_ids: Vec<String> },
+    #[serde(rename_all = "camelCase")]
+    ConnectionGraphUpdate {
+        published_topics: Vec<PublishedTopicMessage>,
+        subscribed_topics: Vec<SubscribedTopicMessage>,
+        advertised_services: Vec<AdvertisedServiceMessage>,
+        removed_topics: Vec<String>,
+        removed_services: Vec<String>,
+    },
 }
 
 pub(crate) type ClientChannelId = u32;
 
+pub(crate) type ClientPublishChannelId = u32;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ClientAdvertiseChannelMessage {
+    pub(crate) id: ClientPublishChannelId,
+    pub(crate) topic: String,
+    pub(crate) encoding: String,
+    pub(crate) schema_name: String,
+    pub(crate) schema: Option<String>,
+    pub(crate) schema_encoding: Option<String>,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct ClientSubscriptionMessage {
@@ -60,43 +146,114 @@ pub(crate) enum ClientMessage {
         subscription_ids: Vec<ClientChannelId>,
     },
     #[serde(rename_all = "camelCase")]
+    Advertise {
+        channels: Vec<ClientAdvertiseChannelMessage>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Unadvertise {
+        channel_ids: Vec<ClientPublishChannelId>,
+    },
+    #[serde(rename_all = "camelCase")]
     GetParameters {
         parameter_names: Vec<String>,
-        #[allow(unused)]
-        id: String,
+        id: Option<String>,
     },
     #[serde(rename_all = "camelCase")]
     SetParameters {
-        parameters: HashMap<String, String>,
-        #[allow(unused)]
-        id: String,
+        parameters: Vec<Parameter>,
+        id: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    SubscribeParameterUpdates {
+        parameter_names: Vec<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    UnsubscribeParameterUpdates {
+        parameter_names: Vec<String>,
+    },
+    SubscribeConnectionGraph,
+    UnsubscribeConnectionGraph,
+    #[serde(rename_all = "camelCase")]
+    FetchAsset {
+        uri: String,
+        request_id: u32,
     },
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub(crate) struct ParameterValue {
-    name: String,
-    value: String,
-    #[serde(rename = "field", skip_serializing_if = "Option::is_none")]
-    field_type: Option<String>,
+/// Binary messages sent from the client to the server. The first byte of each message is the op
+/// code.
+#[derive(Debug)]
+pub(crate) enum ClientBinaryMessage<'a> {
+    MessageData {
+        channel_id: ClientPublishChannelId,
+        data: &'a [u8],
+    },
+    ServiceCallRequest {
+        service_id: ServiceId,
+        call_id: u32,
+        encoding: &'a str,
+        data: &'a [u8],
+    },
 }
 
-impl ParameterValue {
-    pub(crate) fn new(name: &str, value: &str) -> Self {
-        Self {
-            name: name.to_owned(),
-            value: value.to_owned(),
-            field_type: None,
+impl<'a> ClientBinaryMessage<'a> {
+    pub(crate) fn parse(buffer: &'a [u8]) -> anyhow::Result<Self> {
+        let (op_code, payload) = buffer
+            .split_first()
+            .ok_or(anyhow!("Got empty binary message."))?;
+        match op_code {
+            0x01 => {
+                let (channel_id, data) = read_u32(payload)?;
+                Ok(ClientBinaryMessage::MessageData { channel_id, data })
+            }
+            0x02 => {
+                let (service_id, payload) = read_u32(payload)?;
+                let (call_id, payload) = read_u32(payload)?;
+                let (encoding_length, payload) = read_u32(payload)?;
+                let encoding_length = encoding_length as usize;
+                if payload.len() < encoding_length {
+                    return Err(anyhow!("Binary message too short."));
+                }
+                let (encoding, data) = payload.split_at(encoding_length);
+                Ok(ClientBinaryMessage::ServiceCallRequest {
+                    service_id,
+                    call_id,
+                    encoding: std::str::from_utf8(encoding)?,
+                    data,
+                })
+            }
+            _ => Err(anyhow!(
+                "Got binary message with unknown op code {}.",
+                op_code
+            )),
         }
     }
+}
 
-    #[allow(unused)]
-    pub(crate) fn with_type(name: &str, value: &str, field_type: &str) -> Self {
-        Self {
-            name: name.to_owned(),
-            value: value.to_owned(),
-            field_type: Some(field_type.to_owned()),
-        }
+fn read_u32(buffer: &[u8]) -> anyhow::Result<(u32, &[u8])> {
+    if buffer.len() < size_of::<u32>() {
+        return Err(anyhow!("Binary message too short."));
     }
+    let (value, rest) = buffer.split_at(size_of::<u32>());
+    Ok((u32::from_le_bytes(value.try_into()?), rest))
+}
+
+/// Type hint for parameter values that cannot be told apart by their JSON representation.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ParameterType {
+    ByteArray,
+    Float64,
+    Float64Array,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Parameter {
+    pub(crate) name: String,
+    /// Missing values mark deleted parameters.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) value: Option<serde_json::Value>,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub(crate) parameter_type: Option<ParameterType>,
 }
@@ -0,0 +1,752 @@
+//! Outgoing message queue of a client.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    pin::pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::anyhow;
+use tokio::sync::Notify;
+use warp::ws::Message;
+
+use crate::stats::DeliveryCounters;
+
+/// What happens to channel messages for a client whose queue is full, i.e. a client that does
+/// not keep up with receiving the messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BackpressurePolicy {
+    /// Drop the new message.
+    #[default]
+    DropNewest,
+    /// Drop the oldest queued message of the channel to make room for the new one.
+    DropOldest,
+    /// Replace the queued messages of the channel with the new one, so that the client only gets
+    /// the latest message of a busy channel.
+    KeepLatest,
+    /// Disconnect the client.
+    Disconnect,
+}
+
+/// Outcome of queueing a channel message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Delivery {
+    /// The message was queued without dropping anything.
+    Queued,
+    /// The queue was full and a message was dropped, either the new one or a queued one.
+    Dropped,
+    /// The queue was full and the client gets disconnected.
+    Disconnected,
+}
+
+/// Delivery settings and statistics of a channel, shared by all clients.
+#[derive(Debug, Default)]
+pub(crate) struct ChannelDelivery {
+    /// Capacity of the channel's buffer per client or `None` for the client's default.
+    pub(crate) queue_size: Option<usize>,
+    /// Policy for the channel or `None` for the client's policy.
+    pub(crate) backpressure: Option<BackpressurePolicy>,
+    pub(crate) counters: DeliveryCounters,
+}
+
+/// Number of bytes of replies to client requests that may wait in a client's queue. Requests of
+/// the client are not read while more is waiting.
+const MAX_PENDING_REPLY_BYTES: usize = 16 * 1024 * 1024;
+
+#[derive(Debug)]
+struct QueuedMessage {
+    message: Message,
+    /// Channel of the message, if any.
+    channel: Option<Arc<ChannelDelivery>>,
+    /// Whether the message replies to a request of the client.
+    reply: bool,
+}
+
+impl QueuedMessage {
+    fn len(&self) -> usize {
+        self.message.as_bytes().len()
+    }
+}
+
+/// Key of a data buffer: the channel ID for messages of a subscription or `None` for other
+/// messages that may be dropped, like time updates.
+type BufferKey = Option<usize>;
+
+#[derive(Debug, Default)]
+struct QueueState {
+    /// Control messages, e.g. advertisements, parameters and statuses, and replies to requests of
+    /// the client. They are never dropped and always written before any data.
+    control: VecDeque<QueuedMessage>,
+    /// Number of bytes of the replies in `control`.
+    reply_bytes: usize,
+    buffers: HashMap<BufferKey, VecDeque<QueuedMessage>>,
+    /// Buffers holding messages, in the order in which they are drained.
+    ready: VecDeque<BufferKey>,
+    /// Set once the queue stops taking messages, but still has control messages to write.
+    finishing: bool,
+    closed: bool,
+}
+
+impl QueueState {
+    fn len(&self, key: BufferKey) -> usize {
+        self.buffers.get(&key).map_or(0, VecDeque::len)
+    }
+
+    fn enqueue(&mut self, key: BufferKey, message: QueuedMessage) {
+        let buffer = self.buffers.entry(key).or_default();
+        if buffer.is_empty() {
+            self.ready.push_back(key);
+        }
+        buffer.push_back(message);
+    }
+
+    /// Takes the next message. Control messages come first, then the data buffers are visited
+    /// round-robin so that a busy subscription does not hold back the others.
+    fn dequeue(&mut self) -> Option<QueuedMessage> {
+        if let Some(message) = self.control.pop_front() {
+            if message.reply {
+                self.reply_bytes -= message.len();
+            }
+            return Some(message);
+        }
+        while let Some(key) = self.ready.pop_front() {
+            let Some(buffer) = self.buffers.get_mut(&key) else {
+                continue;
+            };
+            let message = buffer.pop_front();
+            if buffer.is_empty() {
+                self.buffers.remove(&key);
+            } else {
+                self.ready.push_back(key);
+            }
+            if message.is_some() {
+                return message;
+            }
+        }
+        None
+    }
+
+    fn remove(&mut self, key: BufferKey) -> VecDeque<QueuedMessage> {
+        self.ready.retain(|ready| *ready != key);
+        self.buffers.remove(&key).unwrap_or_default()
+    }
+}
+
+/// Queue of the messages that are waiting to be written to a client's WebSocket.
+///
+/// Control messages have their own lane, which takes priority over data. Messages the server
+/// sends on its own, like advertisements, are never held back. Replies to requests of the client
+/// share the lane, but only up to a limit: while more replies wait, the client's requests are not
+/// read, see [`ClientQueue::ready_for_request`]. Each subscription of the client has its own
+/// bounded buffer for data and the buffers are drained round-robin. So a slow client only ever
+/// affects itself and a high rate channel does not crowd out the other channels of the client.
+#[derive(Debug)]
+pub(crate) struct ClientQueue {
+    state: Mutex<QueueState>,
+    /// Capacity of the buffers of channels that do not have their own.
+    pub(crate) queue_size: AtomicUsize,
+    /// Policy for channels that do not have their own.
+    pub(crate) backpressure: Mutex<BackpressurePolicy>,
+    /// Statistics of all messages of the client.
+    pub(crate) counters: DeliveryCounters,
+    /// Wakes the writer task when a message was queued or the queue was closed.
+    message_queued: Notify,
+    /// Wakes everyone waiting for the queue to be closed.
+    queue_closed: Notify,
+    /// Wakes the reader of the client's requests when a reply was taken out of the queue.
+    reply_dequeued: Notify,
+}
+
+impl ClientQueue {
+    pub(crate) fn new(queue_size: usize, backpressure: BackpressurePolicy) -> Self {
+        Self {
+            state: Mutex::default(),
+            queue_size: AtomicUsize::new(queue_size),
+            backpressure: Mutex::new(backpressure),
+            counters: DeliveryCounters::default(),
+            message_queued: Notify::new(),
+            queue_closed: Notify::new(),
+            reply_dequeued: Notify::new(),
+        }
+    }
+
+    /// Updates the client's and the channel's counters for a message.
+    fn count(&self, message: &QueuedMessage, update: fn(&DeliveryCounters, usize)) {
+        let bytes = message.len();
+        update(&self.counters, bytes);
+        if let Some(channel) = &message.channel {
+            update(&channel.counters, bytes);
+        }
+    }
+
+    /// Queues a control message the server sends on its own. Control messages are never dropped,
+    /// so this never waits for the client.
+    pub(crate) fn send(&self, message: Message) -> anyhow::Result<()> {
+        self.push_control(message, false)
+    }
+
+    /// Queues the reply to a request of the client. Replies are never dropped, instead the
+    /// client's requests are not read while too many replies wait.
+    pub(crate) fn reply(&self, message: Message) -> anyhow::Result<()> {
+        self.push_control(message, true)
+    }
+
+    fn push_control(&self, message: Message, reply: bool) -> anyhow::Result<()> {
+        let message = QueuedMessage {
+            message,
+            channel: None,
+            reply,
+        };
+        let mut state = self.state.lock().unwrap();
+        if state.closed || state.finishing {
+            return Err(anyhow!("Client queue is closed."));
+        }
+        self.count(&message, DeliveryCounters::queued);
+        if reply {
+            state.reply_bytes += message.len();
+        }
+        state.control.push_back(message);
+        self.message_queued.notify_one();
+        Ok(())
+    }
+
+    /// Waits until few enough replies wait in the queue to take the next request of the client,
+    /// or until the queue is closed.
+    pub(crate) async fn ready_for_request(&self) {
+        loop {
+            let mut reply_dequeued = pin!(self.reply_dequeued.notified());
+            reply_dequeued.as_mut().enable();
+            {
+                let state = self.state.lock().unwrap();
+                if state.closed || state.reply_bytes < MAX_PENDING_REPLY_BYTES {
+                    return;
+                }
+            }
+            reply_dequeued.await;
+        }
+    }
+
+    /// Queues a data message that does not belong to a channel if there is room for it.
+    pub(crate) fn try_send(&self, message: Message) -> anyhow::Result<()> {
+        let message = QueuedMessage {
+            message,
+            channel: None,
+            reply: false,
+        };
+        let mut state = self.state.lock().unwrap();
+        if state.closed || state.finishing {
+            return Err(anyhow!("Client queue is closed."));
+        }
+        if state.len(None) >= self.queue_size.load(Ordering::Relaxed) {
+            self.count(&message, DeliveryCounters::dropped);
+            return Err(anyhow!("Client queue is full."));
+        }
+        self.count(&message, DeliveryCounters::queued);
+        state.enqueue(None, message);
+        self.message_queued.notify_one();
+        Ok(())
+    }
+
+    /// Queues a message of a channel. The channel's queue size and policy override the client's
+    /// settings.
+    pub(crate) fn push(
+        &self,
+        channel_id: usize,
+        message: Message,
+        channel: &Arc<ChannelDelivery>,
+    ) -> anyhow::Result<Delivery> {
+        let queue_size = channel
+            .queue_size
+            .unwrap_or_else(|| self.queue_size.load(Ordering::Relaxed));
+        let backpressure = channel
+            .backpressure
+            .unwrap_or_else(|| *self.backpressure.lock().unwrap());
+        let message = QueuedMessage {
+            message,
+            channel: Some(channel.clone()),
+            reply: false,
+        };
+        let key = Some(channel_id);
+        let mut state = self.state.lock().unwrap();
+        if state.closed || state.finishing {
+            return Err(anyhow!("Client queue is closed."));
+        }
+
+        if state.len(key) < queue_size {
+            self.count(&message, DeliveryCounters::queued);
+            state.enqueue(key, message);
+            self.message_queued.notify_one();
+            return Ok(Delivery::Queued);
+        }
+        // The buffer is not empty, so it is already lined up to be drained.
+        let buffer = state.buffers.entry(key).or_default();
+        let dropped: Vec<_> = match backpressure {
+            BackpressurePolicy::DropNewest => vec![message],
+            BackpressurePolicy::DropOldest => {
+                let oldest = buffer.pop_front();
+                self.count(&message, DeliveryCounters::queued);
+                buffer.push_back(message);
+                oldest.into_iter().collect()
+            }
+            BackpressurePolicy::KeepLatest => {
+                let older = buffer.drain(..).collect();
+                self.count(&message, DeliveryCounters::queued);
+                buffer.push_back(message);
+                older
+            }
+            BackpressurePolicy::Disconnect => {
+                drop(state);
+                self.count(&message, DeliveryCounters::dropped);
+                self.close();
+                return Ok(Delivery::Disconnected);
+            }
+        };
+        for message in &dropped {
+            if backpressure != BackpressurePolicy::DropNewest {
+                self.count(message, DeliveryCounters::dequeued);
+            }
+            self.count(message, DeliveryCounters::dropped);
+        }
+        Ok(Delivery::Dropped)
+    }
+
+    /// Discards the queued messages of a channel, e.g. after the client unsubscribed.
+    pub(crate) fn remove_channel(&self, channel_id: usize) {
+        let removed = self.state.lock().unwrap().remove(Some(channel_id));
+        for message in &removed {
+            self.count(message, DeliveryCounters::dequeued);
+            self.count(message, DeliveryCounters::dropped);
+        }
+    }
+
+    /// Takes the next message out of the queue, waiting for one if the queue is empty. Returns
+    /// `None` once the queue is closed. The message comes with its channel, if any.
+    pub(crate) async fn pop(&self) -> Option<(Message, Option<Arc<ChannelDelivery>>)> {
+        loop {
+            let message_queued = self.message_queued.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.closed {
+                    return None;
+                }
+                if let Some(message) = state.dequeue() {
+                    self.count(&message, DeliveryCounters::dequeued);
+                    if message.reply {
+                        self.reply_dequeued.notify_waiters();
+                    }
+                    return Some((message.message, message.channel));
+                }
+                if state.finishing {
+                    return None;
+                }
+            }
+            message_queued.await;
+        }
+    }
+
+    /// Tracks a message taken out of the queue while it is written.
+    pub(crate) fn in_flight(
+        &self,
+        message: &Message,
+        channel: Option<Arc<ChannelDelivery>>,
+    ) -> InFlight<'_> {
+        InFlight {
+            queue: self,
+            channel,
+            bytes: message.as_bytes().len(),
+            sent: false,
+        }
+    }
+
+    /// Counts a message taken out of the queue as sent.
+    pub(crate) fn sent(&self, channel: Option<&ChannelDelivery>, bytes: usize) {
+        self.counters.sent(bytes);
+        if let Some(channel) = channel {
+            channel.counters.sent(bytes);
+        }
+    }
+
+    /// Counts a message taken out of the queue as dropped, e.g. because writing it failed.
+    pub(crate) fn dropped(&self, channel: Option<&ChannelDelivery>, bytes: usize) {
+        self.counters.dropped(bytes);
+        if let Some(channel) = channel {
+            channel.counters.dropped(bytes);
+        }
+    }
+
+    /// Stops taking messages, e.g. when the server shuts down. Queued control messages are still
+    /// handed out, queued data is discarded.
+    pub(crate) fn finish(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.finishing = true;
+        state.ready.clear();
+        let buffers = std::mem::take(&mut state.buffers);
+        for message in buffers.values().flatten() {
+            self.count(message, DeliveryCounters::dequeued);
+            self.count(message, DeliveryCounters::dropped);
+        }
+        self.message_queued.notify_one();
+    }
+
+    /// Closes the queue. Queued messages are discarded and senders fail from now on.
+    pub(crate) fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        state.ready.clear();
+        state.reply_bytes = 0;
+        let control = std::mem::take(&mut state.control);
+        let buffers = std::mem::take(&mut state.buffers);
+        for message in control.iter().chain(buffers.values().flatten()) {
+            self.count(message, DeliveryCounters::dequeued);
+            self.count(message, DeliveryCounters::dropped);
+        }
+        self.message_queued.notify_one();
+        self.queue_closed.notify_waiters();
+        self.reply_dequeued.notify_waiters();
+    }
+
+    /// Waits until the queue is closed.
+    pub(crate) async fn closed(&self) {
+        let mut queue_closed = pin!(self.queue_closed.notified());
+        queue_closed.as_mut().enable();
+        if self.state.lock().unwrap().closed {
+            return;
+        }
+        queue_closed.await;
+    }
+}
+
+/// A message taken out of a client's queue that is being written. It counts as dropped unless it
+/// is marked as sent, so that a message is not lost from the statistics when writing it fails or
+/// the writer is aborted, e.g. when the client is disconnected.
+#[derive(Debug)]
+pub(crate) struct InFlight<'a> {
+    queue: &'a ClientQueue,
+    channel: Option<Arc<ChannelDelivery>>,
+    bytes: usize,
+    sent: bool,
+}
+
+impl InFlight<'_> {
+    /// Counts the message as sent.
+    pub(crate) fn sent(mut self) {
+        self.queue.sent(self.channel.as_deref(), self.bytes);
+        self.sent = true;
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        if !self.sent {
+            self.queue.dropped(self.channel.as_deref(), self.bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+
+    use super::*;
+    use crate::{stats::DeliveryStats, Client, FoxgloveWebSocket, PublisherOptions};
+
+    fn channel(queue_size: usize, backpressure: BackpressurePolicy) -> Arc<ChannelDelivery> {
+        Arc::new(ChannelDelivery {
+            queue_size: Some(queue_size),
+            backpressure: Some(backpressure),
+            counters: DeliveryCounters::default(),
+        })
+    }
+
+    /// Takes the messages that are queued right now.
+    fn drain(queue: &ClientQueue) -> Vec<String> {
+        let mut messages = Vec::new();
+        while let Some(Some((message, _))) = queue.pop().now_or_never() {
+            messages.push(message.to_str().unwrap().to_owned());
+        }
+        messages
+    }
+
+    /// Queues the messages `1` to `count` on a channel with room for two messages.
+    fn fill(backpressure: BackpressurePolicy, count: usize) -> (ClientQueue, Vec<Delivery>) {
+        let queue = ClientQueue::new(16, BackpressurePolicy::default());
+        let channel = channel(2, backpressure);
+        let deliveries = (1..=count)
+            .map(|i| {
+                queue
+                    .push(0, Message::text(i.to_string()), &channel)
+                    .unwrap()
+            })
+            .collect();
+        (queue, deliveries)
+    }
+
+    #[test]
+    fn drop_newest_keeps_the_queued_messages() {
+        let (queue, deliveries) = fill(BackpressurePolicy::DropNewest, 4);
+        use Delivery::*;
+        assert_eq!(deliveries, [Queued, Queued, Dropped, Dropped]);
+        assert_eq!(drain(&queue), ["1", "2"]);
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_newest_messages() {
+        let (queue, deliveries) = fill(BackpressurePolicy::DropOldest, 4);
+        use Delivery::*;
+        assert_eq!(deliveries, [Queued, Queued, Dropped, Dropped]);
+        assert_eq!(drain(&queue), ["3", "4"]);
+    }
+
+    #[test]
+    fn keep_latest_keeps_the_latest_message() {
+        // A full buffer is replaced by the new message, leaving room for the next one.
+        let (queue, deliveries) = fill(BackpressurePolicy::KeepLatest, 5);
+        use Delivery::*;
+        assert_eq!(deliveries, [Queued, Queued, Dropped, Queued, Dropped]);
+        assert_eq!(drain(&queue), ["5"]);
+    }
+
+    #[test]
+    fn disconnect_closes_the_queue() {
+        let (queue, deliveries) = fill(BackpressurePolicy::Disconnect, 3);
+        use Delivery::*;
+        assert_eq!(deliveries, [Queued, Queued, Disconnected]);
+        assert!(matches!(queue.pop().now_or_never(), Some(None)));
+        let channel = channel(2, BackpressurePolicy::Disconnect);
+        assert!(queue.push(0, Message::text("4"), &channel).is_err());
+        assert!(queue.send(Message::text("control")).is_err());
+    }
+
+    #[test]
+    fn channel_settings_override_the_client_settings() {
+        let queue = ClientQueue::new(1, BackpressurePolicy::DropNewest);
+        let own = channel(2, BackpressurePolicy::DropOldest);
+        let default = Arc::new(ChannelDelivery::default());
+        for i in 1..=3 {
+            queue
+                .push(0, Message::text(format!("own {}", i)), &own)
+                .unwrap();
+            queue
+                .push(1, Message::text(format!("default {}", i)), &default)
+                .unwrap();
+        }
+        assert_eq!(drain(&queue), ["own 2", "default 1", "own 3"]);
+    }
+
+    #[test]
+    fn drains_channels_round_robin() {
+        let queue = ClientQueue::new(16, BackpressurePolicy::default());
+        let busy = Arc::new(ChannelDelivery::default());
+        let quiet = Arc::new(ChannelDelivery::default());
+        for i in 1..=3 {
+            queue
+                .push(0, Message::text(format!("busy {}", i)), &busy)
+                .unwrap();
+        }
+        queue.push(1, Message::text("quiet 1"), &quiet).unwrap();
+        queue.try_send(Message::text("time")).unwrap();
+        queue.push(1, Message::text("quiet 2"), &quiet).unwrap();
+        assert_eq!(
+            drain(&queue),
+            ["busy 1", "quiet 1", "time", "busy 2", "quiet 2", "busy 3"]
+        );
+    }
+
+    #[test]
+    fn counts_messages() {
+        let (queue, _) = fill(BackpressurePolicy::DropOldest, 3);
+        let (message, channel) = queue.pop().now_or_never().unwrap().unwrap();
+        queue.sent(channel.as_deref(), message.as_bytes().len());
+
+        // "1" was dropped, "2" was sent and "3" still waits.
+        let expected = DeliveryStats {
+            sent_messages: 1,
+            sent_bytes: 1,
+            dropped_messages: 1,
+            dropped_bytes: 1,
+            queued_messages: 1,
+            queued_bytes: 1,
+        };
+        assert_eq!(queue.counters.snapshot(), expected);
+        assert_eq!(channel.unwrap().counters.snapshot(), expected);
+
+        queue.send(Message::text("control")).unwrap();
+        queue.remove_channel(0);
+        let stats = queue.counters.snapshot();
+        assert_eq!(stats.dropped_messages, 2);
+        assert_eq!((stats.queued_messages, stats.queued_bytes), (1, 7));
+
+        queue.close();
+        let stats = queue.counters.snapshot();
+        assert_eq!(stats.dropped_messages, 3);
+        assert_eq!((stats.queued_messages, stats.queued_bytes), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn clamps_queue_sizes() {
+        let server = FoxgloveWebSocket::builder().client_queue_size(0).build();
+        assert_eq!(server.config.client_queue_size, 1);
+
+        let channel = server
+            .create_publisher_with_options(
+                "/topic",
+                "json",
+                "Type",
+                "{}",
+                None,
+                PublisherOptions {
+                    queue_size: Some(0),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(channel.delivery.queue_size, Some(1));
+
+        let client_id = add_client(&server, &channel, 4, BackpressurePolicy::default()).await;
+        server.set_client_queue_size(&client_id, 0).await.unwrap();
+        let clients = server.clients.clients.read().await;
+        assert_eq!(clients[&client_id].tx.queue_size.load(Ordering::Relaxed), 1);
+    }
+
+    /// Adds a client that is subscribed to `channel` without connecting one.
+    async fn add_client(
+        server: &FoxgloveWebSocket,
+        channel: &crate::Channel,
+        queue_size: usize,
+        backpressure: BackpressurePolicy,
+    ) -> uuid::Uuid {
+        let id = uuid::Uuid::new_v4();
+        let client = Client {
+            id,
+            identity: None,
+            tx: Arc::new(ClientQueue::new(queue_size, backpressure)),
+            subscriptions: [(channel.id, 0)].into(),
+            advertisements: HashMap::new(),
+            parameter_subscriptions: Default::default(),
+            subscribed_connection_graph: false,
+            asset_fetches: Arc::new(tokio::sync::Semaphore::new(1)),
+            service_calls: Arc::new(tokio::sync::Semaphore::new(1)),
+        };
+        server.clients.clients.write().await.insert(id, client);
+        id
+    }
+
+    #[tokio::test]
+    async fn slow_clients_do_not_hold_back_the_others() {
+        let server = FoxgloveWebSocket::new("test");
+        let channel = server
+            .create_publisher("/topic", "json", "Type", "{}", None, false)
+            .await
+            .unwrap();
+        // Clients are visited in no particular order, so there are several of each kind.
+        let mut slow = Vec::new();
+        let mut gone = Vec::new();
+        let mut healthy = Vec::new();
+        for _ in 0..4 {
+            slow.push(add_client(&server, &channel, 1, BackpressurePolicy::DropNewest).await);
+            gone.push(add_client(&server, &channel, 1, BackpressurePolicy::Disconnect).await);
+            healthy.push(add_client(&server, &channel, 16, BackpressurePolicy::DropNewest).await);
+        }
+        for id in &gone {
+            server.clients.clients.read().await[id].tx.close();
+        }
+
+        for timestamp in 0..3 {
+            channel.send(timestamp, b"data").await.unwrap();
+        }
+
+        let clients = server.clients.clients.read().await;
+        for id in &healthy {
+            let stats = clients[id].tx.counters.snapshot();
+            assert_eq!((stats.queued_messages, stats.dropped_messages), (3, 0));
+        }
+        for id in &slow {
+            let stats = clients[id].tx.counters.snapshot();
+            assert_eq!((stats.queued_messages, stats.dropped_messages), (1, 2));
+        }
+    }
+
+    #[test]
+    fn control_messages_overtake_waiting_data() {
+        let (queue, _) = fill(BackpressurePolicy::DropNewest, 2);
+        queue.try_send(Message::text("time")).unwrap();
+        queue.send(Message::text("advertise")).unwrap();
+        queue.reply(Message::text("reply")).unwrap();
+        queue.send(Message::text("status")).unwrap();
+        assert_eq!(
+            drain(&queue),
+            ["advertise", "reply", "status", "1", "time", "2"]
+        );
+    }
+
+    #[test]
+    fn control_messages_survive_full_buffers() {
+        let (queue, deliveries) = fill(BackpressurePolicy::DropNewest, 3);
+        assert_eq!(deliveries.last(), Some(&Delivery::Dropped));
+        for _ in 0..queue.queue_size.load(Ordering::Relaxed) {
+            queue.try_send(Message::text("time")).unwrap();
+        }
+        assert!(queue.try_send(Message::text("time")).is_err());
+
+        for i in 0..100 {
+            queue.send(Message::text(format!("status {}", i))).unwrap();
+        }
+        let messages = drain(&queue);
+        assert_eq!(messages.len(), 100 + 2 + 16);
+        assert!(messages[..100]
+            .iter()
+            .enumerate()
+            .all(|(i, message)| *message == format!("status {}", i)));
+    }
+
+    #[tokio::test]
+    async fn holds_back_requests_while_replies_wait() {
+        let queue = ClientQueue::new(16, BackpressurePolicy::default());
+        queue
+            .reply(Message::binary(vec![0; MAX_PENDING_REPLY_BYTES - 1]))
+            .unwrap();
+        assert!(queue.ready_for_request().now_or_never().is_some());
+
+        queue.reply(Message::binary(vec![0; 1])).unwrap();
+        // Messages the server sends on its own do not count.
+        queue.send(Message::text("advertise")).unwrap();
+        let mut ready = pin!(queue.ready_for_request());
+        assert!(ready.as_mut().now_or_never().is_none());
+
+        queue.pop().await.unwrap();
+        ready.await;
+        assert_eq!(queue.state.lock().unwrap().reply_bytes, 1);
+
+        // A closed queue does not hold back anything.
+        queue
+            .reply(Message::binary(vec![0; MAX_PENDING_REPLY_BYTES]))
+            .unwrap();
+        queue.close();
+        assert!(queue.ready_for_request().now_or_never().is_some());
+    }
+
+    #[test]
+    fn counts_unwritten_messages_as_dropped() {
+        let (queue, _) = fill(BackpressurePolicy::DropNewest, 2);
+        for _ in 0..2 {
+            let (message, channel) = queue.pop().now_or_never().unwrap().unwrap();
+            let in_flight = queue.in_flight(&message, channel);
+            if message.to_str().unwrap() == "1" {
+                in_flight.sent();
+            }
+        }
+        let stats = queue.counters.snapshot();
+        assert_eq!(
+            (
+                stats.sent_messages,
+                stats.dropped_messages,
+                stats.queued_messages
+            ),
+            (1, 1, 0)
+        );
+    }
+}
@@ -0,0 +1,1063 @@
+//! ROS 1 messages described by `.msg` definitions.
+//!
+//! [`MessageDefinitions`] loads the definitions of message types, e.g. from the `msg` directories
+//! of ROS packages. It builds the `ros1msg` schema of a message type, which holds the definitions
+//! of all message types it depends on, and serializes messages given as JSON values to the ROS 1
+//! wire format.
+//!
+//! # Example
+//!
+//! ```
+//! # #[tokio::main]
+//! # async fn main() -> anyhow::Result<()> {
+//! use foxglove_ws::ros1::MessageDefinitions;
+//! use serde_json::json;
+//!
+//! let mut definitions = MessageDefinitions::new();
+//! definitions.add(
+//!     "std_msgs/Header",
+//!     "uint32 seq\ntime stamp\nstring frame_id",
+//! )?;
+//! definitions.add("geometry_msgs/Point", "float64 x\nfloat64 y\nfloat64 z")?;
+//! definitions.add("geometry_msgs/PointStamped", "Header header\nPoint point")?;
+//!
+//! let server = foxglove_ws::FoxgloveWebSocket::new("robot");
+//! let channel = server
+//!     .create_publisher(
+//!         "/target",
+//!         "ros1",
+//!         "geometry_msgs/PointStamped",
+//!         definitions.schema("geometry_msgs/PointStamped")?,
+//!         Some("ros1msg"),
+//!         false,
+//!     )
+//!     .await?;
+//! let message = json!({
+//!     "header": { "seq": 0, "stamp": { "sec": 1, "nsec": 0 }, "frame_id": "map" },
+//!     "point": { "x": 1.0, "y": 2.0, "z": 0.0 },
+//! });
+//! channel
+//!     .send(
+//!         1_000_000_000,
+//!         &definitions.serialize("geometry_msgs/PointStamped", &message)?,
+//!     )
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    marker::PhantomData,
+    path::Path,
+};
+
+use anyhow::anyhow;
+use serde_json::Value;
+
+use crate::{MessageEncoding, SchemaDescriptor};
+
+// Documented in foxglove-ws-derive, rustdoc shows its documentation here.
+#[cfg(feature = "derive")]
+pub use foxglove_ws_derive::Ros1Message;
+
+#[doc(hidden)]
+pub mod __private {
+    pub use anyhow::Result;
+}
+
+/// Line separating the definitions of the message types in a `ros1msg` schema.
+const SEPARATOR: &str =
+    "================================================================================";
+
+/// Type of a field of a ROS 1 message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FieldType {
+    /// `bool`.
+    Bool,
+    /// `int8`, or its deprecated alias `byte`.
+    Int8,
+    /// `uint8`, or its deprecated alias `char`.
+    UInt8,
+    /// `int16`.
+    Int16,
+    /// `uint16`.
+    UInt16,
+    /// `int32`.
+    Int32,
+    /// `uint32`.
+    UInt32,
+    /// `int64`.
+    Int64,
+    /// `uint64`.
+    UInt64,
+    /// `float32`.
+    Float32,
+    /// `float64`.
+    Float64,
+    /// `string`.
+    String,
+    /// `time`, seconds and nanoseconds since the epoch.
+    Time,
+    /// `duration`, signed seconds and nanoseconds.
+    Duration,
+    /// Another message type, with its full name, e.g. `std_msgs/Header`.
+    Message(String),
+    /// Array of elements, with a fixed length or with a variable length if `None`.
+    Array(Box<FieldType>, Option<usize>),
+}
+
+impl FieldType {
+    /// Parses a type of a field in a definition of a message type of `package`.
+    fn parse(type_name: &str, package: &str) -> anyhow::Result<Self> {
+        if let Some((element, length)) = type_name.strip_suffix(']').and_then(|t| t.split_once('['))
+        {
+            let length = match length {
+                "" => None,
+                length => Some(
+                    length
+                        .parse()
+                        .map_err(|_| anyhow!("Invalid array length {}.", length))?,
+                ),
+            };
+            return Ok(FieldType::Array(
+                Box::new(FieldType::parse(element, package)?),
+                length,
+            ));
+        }
+        Ok(match type_name {
+            "bool" => FieldType::Bool,
+            "int8" | "byte" => FieldType::Int8,
+            "uint8" | "char" => FieldType::UInt8,
+            "int16" => FieldType::Int16,
+            "uint16" => FieldType::UInt16,
+            "int32" => FieldType::Int32,
+            "uint32" => FieldType::UInt32,
+            "int64" => FieldType::Int64,
+            "uint64" => FieldType::UInt64,
+            "float32" => FieldType::Float32,
+            "float64" => FieldType::Float64,
+            "string" => FieldType::String,
+            "time" => FieldType::Time,
+            "duration" => FieldType::Duration,
+            "Header" => FieldType::Message("std_msgs/Header".to_owned()),
+            name if name.contains('/') => FieldType::Message(name.to_owned()),
+            name if is_identifier(name) => FieldType::Message(format!("{}/{}", package, name)),
+            name => return Err(anyhow!("Invalid type {}.", name)),
+        })
+    }
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldType::Bool => write!(f, "bool"),
+            FieldType::Int8 => write!(f, "int8"),
+            FieldType::UInt8 => write!(f, "uint8"),
+            FieldType::Int16 => write!(f, "int16"),
+            FieldType::UInt16 => write!(f, "uint16"),
+            FieldType::Int32 => write!(f, "int32"),
+            FieldType::UInt32 => write!(f, "uint32"),
+            FieldType::Int64 => write!(f, "int64"),
+            FieldType::UInt64 => write!(f, "uint64"),
+            FieldType::Float32 => write!(f, "float32"),
+            FieldType::Float64 => write!(f, "float64"),
+            FieldType::String => write!(f, "string"),
+            FieldType::Time => write!(f, "time"),
+            FieldType::Duration => write!(f, "duration"),
+            FieldType::Message(name) => write!(f, "{}", name),
+            FieldType::Array(element, Some(length)) => write!(f, "{}[{}]", element, length),
+            FieldType::Array(element, None) => write!(f, "{}[]", element),
+        }
+    }
+}
+
+/// A field of a ROS 1 message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Field {
+    /// Name of the field.
+    pub name: String,
+    /// Type of the field.
+    pub field_type: FieldType,
+}
+
+/// The definition of a ROS 1 message type, as given in a `.msg` file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MessageDefinition {
+    /// Full name of the message type, e.g. `std_msgs/String`.
+    pub name: String,
+    /// Fields of the message type in the order they are serialized. Constants are not included.
+    pub fields: Vec<Field>,
+    /// Text of the definition.
+    pub text: String,
+}
+
+impl MessageDefinition {
+    /// Creates the definition of a message type from its fields.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Full name of the message type, e.g. `std_msgs/String`.
+    /// * `fields` - Fields of the message type in the order they are serialized.
+    pub fn new(name: &str, fields: Vec<Field>) -> Self {
+        let text = fields
+            .iter()
+            .map(|field| format!("{} {}", field.field_type, field.name))
+            .collect::<Vec<_>>()
+            .join("\n");
+        Self {
+            name: name.to_owned(),
+            fields,
+            text,
+        }
+    }
+
+    /// Parses the definition of a message type.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Full name of the message type, e.g. `std_msgs/String`. Types of fields without
+    ///   a package refer to message types of the same package.
+    /// * `text` - Content of the `.msg` file.
+    pub fn parse(name: &str, text: &str) -> anyhow::Result<Self> {
+        let (package, _) = name
+            .split_once('/')
+            .filter(|(package, type_name)| is_identifier(package) && is_identifier(type_name))
+            .ok_or(anyhow!("Invalid message type name {}.", name))?;
+        let mut fields = vec![];
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let field = parse_field(line, package)
+                .map_err(|err| anyhow!("Line {} of {}: {}", index + 1, name, err))?;
+            fields.extend(field);
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            fields,
+            text: text.to_owned(),
+        })
+    }
+}
+
+/// Parses a line declaring a field or a constant. Returns `None` for constants.
+fn parse_field(line: &str, package: &str) -> anyhow::Result<Option<Field>> {
+    let (type_name, rest) = line
+        .split_once(char::is_whitespace)
+        .ok_or(anyhow!("Missing field name."))?;
+    // String constants may contain `#`, so constants are recognized before removing comments.
+    let declaration = rest.split('#').next().unwrap_or_default();
+    if declaration.contains('=') {
+        return Ok(None);
+    }
+    let name = declaration.trim();
+    if !is_identifier(name) {
+        return Err(anyhow!("Invalid field name {}.", name));
+    }
+    Ok(Some(Field {
+        name: name.to_owned(),
+        field_type: FieldType::parse(type_name, package)?,
+    }))
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Definitions of ROS 1 message types, to build schemas and serialize messages with.
+#[derive(Clone, Debug, Default)]
+pub struct MessageDefinitions {
+    definitions: HashMap<String, MessageDefinition>,
+}
+
+impl MessageDefinitions {
+    /// Creates an empty set of definitions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the definition of a message type, replacing an earlier definition of it.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Full name of the message type, e.g. `std_msgs/String`.
+    /// * `text` - Content of the `.msg` file.
+    pub fn add(&mut self, name: &str, text: &str) -> anyhow::Result<()> {
+        self.insert(MessageDefinition::parse(name, text)?);
+        Ok(())
+    }
+
+    /// Adds a parsed definition of a message type, replacing an earlier definition of it.
+    pub fn insert(&mut self, definition: MessageDefinition) {
+        self.definitions.insert(definition.name.clone(), definition);
+    }
+
+    /// Adds the definitions of the message types in the `msg` directory of a ROS package.
+    ///
+    /// The name of the package is taken from its `package.xml`, or else from the name of the
+    /// directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Directory of the package.
+    pub async fn load_package(&mut self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let package = package_name(path).await?;
+        let msg_path = path.join("msg");
+        let mut entries = tokio::fs::read_dir(&msg_path)
+            .await
+            .map_err(|err| anyhow!("Failed to read {}: {}.", msg_path.display(), err))?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_none_or(|extension| extension != "msg") {
+                continue;
+            }
+            let Some(type_name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let text = tokio::fs::read_to_string(&path)
+                .await
+                .map_err(|err| anyhow!("Failed to read {}: {}.", path.display(), err))?;
+            self.add(&format!("{}/{}", package, type_name), &text)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the definition of a message type.
+    pub fn get(&self, name: &str) -> Option<&MessageDefinition> {
+        self.definitions.get(name)
+    }
+
+    /// Returns the `ros1msg` schema of a message type. It holds the definition of the message
+    /// type followed by the definitions of all message types it depends on, each separated by a
+    /// line of `=` and a `MSG: <name>` line. Fails if a definition is missing.
+    pub fn schema(&self, name: &str) -> anyhow::Result<String> {
+        let mut schema = self.definition(name)?.text.trim_end().to_owned();
+        let mut dependencies = vec![];
+        self.collect_dependencies(name, &mut HashSet::from([name]), &mut dependencies)?;
+        for definition in dependencies {
+            schema.push_str(&format!(
+                "\n{}\nMSG: {}\n{}",
+                SEPARATOR,
+                definition.name,
+                definition.text.trim_end()
+            ));
+        }
+        Ok(schema)
+    }
+
+    /// Serializes a message to the ROS 1 wire format.
+    ///
+    /// Messages are given as JSON objects with a value for each field: booleans, numbers and
+    /// strings for the primitive types, arrays for arrays, objects for nested messages and
+    /// objects with `sec` and `nsec` for `time` and `duration`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Full name of the message type.
+    /// * `message` - Values of the fields.
+    pub fn serialize(&self, name: &str, message: &Value) -> anyhow::Result<Vec<u8>> {
+        let mut buffer = vec![];
+        self.write_message(name, message, name, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn definition(&self, name: &str) -> anyhow::Result<&MessageDefinition> {
+        self.definitions
+            .get(name)
+            .ok_or(anyhow!("Unknown message type {}.", name))
+    }
+
+    /// Collects the definitions of the message types `name` depends on, depth first in the order
+    /// of the fields.
+    fn collect_dependencies<'a>(
+        &'a self,
+        name: &str,
+        seen: &mut HashSet<&'a str>,
+        dependencies: &mut Vec<&'a MessageDefinition>,
+    ) -> anyhow::Result<()> {
+        for field in &self.definition(name)?.fields {
+            let mut field_type = &field.field_type;
+            while let FieldType::Array(element, _) = field_type {
+                field_type = element;
+            }
+            let FieldType::Message(dependency) = field_type else {
+                continue;
+            };
+            let definition = self
+                .definition(dependency)
+                .map_err(|err| anyhow!("{} Used by {}.", err, name))?;
+            if seen.insert(&definition.name) {
+                dependencies.push(definition);
+                self.collect_dependencies(dependency, seen, dependencies)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_message(
+        &self,
+        name: &str,
+        message: &Value,
+        path: &str,
+        buffer: &mut Vec<u8>,
+    ) -> anyhow::Result<()> {
+        let definition = self.definition(name)?;
+        let message = message
+            .as_object()
+            .ok_or(anyhow!("Expected an object for {}.", path))?;
+        for field in &definition.fields {
+            let path = format!("{}.{}", path, field.name);
+            let value = message
+                .get(&field.name)
+                .ok_or(anyhow!("Missing field {}.", path))?;
+            self.write_value(&field.field_type, value, &path, buffer)?;
+        }
+        Ok(())
+    }
+
+    fn write_value(
+        &self,
+        field_type: &FieldType,
+        value: &Value,
+        path: &str,
+        buffer: &mut Vec<u8>,
+    ) -> anyhow::Result<()> {
+        match field_type {
+            FieldType::Bool => {
+                let value = value
+                    .as_bool()
+                    .ok_or(anyhow!("Expected a boolean for {}.", path))?;
+                buffer.push(value.into());
+            }
+            FieldType::Int8 => buffer.extend(int::<i8>(value, path)?.to_le_bytes()),
+            FieldType::UInt8 => buffer.extend(int::<u8>(value, path)?.to_le_bytes()),
+            FieldType::Int16 => buffer.extend(int::<i16>(value, path)?.to_le_bytes()),
+            FieldType::UInt16 => buffer.extend(int::<u16>(value, path)?.to_le_bytes()),
+            FieldType::Int32 => buffer.extend(int::<i32>(value, path)?.to_le_bytes()),
+            FieldType::UInt32 => buffer.extend(int::<u32>(value, path)?.to_le_bytes()),
+            FieldType::Int64 => buffer.extend(int::<i64>(value, path)?.to_le_bytes()),
+            FieldType::UInt64 => buffer.extend(int::<u64>(value, path)?.to_le_bytes()),
+            FieldType::Float32 => buffer.extend((float(value, path)? as f32).to_le_bytes()),
+            FieldType::Float64 => buffer.extend(float(value, path)?.to_le_bytes()),
+            FieldType::String => {
+                let value = value
+                    .as_str()
+                    .ok_or(anyhow!("Expected a string for {}.", path))?;
+                write_length(value.len(), path, buffer)?;
+                buffer.extend(value.as_bytes());
+            }
+            FieldType::Time => {
+                let (sec, nsec) = time(value, path)?;
+                buffer.extend(int::<u32>(sec, &format!("{}.sec", path))?.to_le_bytes());
+                buffer.extend(int::<u32>(nsec, &format!("{}.nsec", path))?.to_le_bytes());
+            }
+            FieldType::Duration => {
+                let (sec, nsec) = time(value, path)?;
+                buffer.extend(int::<i32>(sec, &format!("{}.sec", path))?.to_le_bytes());
+                buffer.extend(int::<i32>(nsec, &format!("{}.nsec", path))?.to_le_bytes());
+            }
+            FieldType::Message(name) => self.write_message(name, value, path, buffer)?,
+            FieldType::Array(element, length) => {
+                let elements = value
+                    .as_array()
+                    .ok_or(anyhow!("Expected an array for {}.", path))?;
+                match length {
+                    Some(length) if elements.len() != *length => {
+                        return Err(anyhow!(
+                            "Expected {} elements for {}, got {}.",
+                            length,
+                            path,
+                            elements.len()
+                        ));
+                    }
+                    Some(_) => {}
+                    None => write_length(elements.len(), path, buffer)?,
+                }
+                for (index, value) in elements.iter().enumerate() {
+                    self.write_value(element, value, &format!("{}[{}]", path, index), buffer)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A Rust type serialized as a field of a ROS 1 message.
+pub trait Ros1Field {
+    /// Returns the ROS 1 type of the field.
+    fn field_type() -> FieldType;
+
+    /// Adds the definitions of the message types the field consists of.
+    fn add_definitions(_definitions: &mut MessageDefinitions) {}
+
+    /// Serializes the value to the ROS 1 wire format.
+    fn write(&self, buffer: &mut Vec<u8>) -> anyhow::Result<()>;
+}
+
+/// A ROS 1 message type, usually implemented with `#[derive(Ros1Message)]`.
+///
+/// Message types can be published with [`crate::FoxgloveWebSocket::create_typed_publisher`] and
+/// the [`Ros1`] encoding. Derived message types implement [`MessageEncoding`] themselves.
+pub trait Ros1Message: Ros1Field {
+    /// Returns the definition of the message type.
+    fn definition() -> MessageDefinition;
+}
+
+/// The ROS 1 encoding of messages of type `T`, with the `ros1msg` schema of `T` and the
+/// definitions it depends on. Publish messages with it e.g. with
+/// `server.create_typed_publisher::<Ros1<T>>("/topic", false)`.
+pub struct Ros1<T>(PhantomData<T>);
+
+impl<T: Ros1Message> MessageEncoding for Ros1<T> {
+    type Message = T;
+
+    fn encoding() -> String {
+        "ros1".to_owned()
+    }
+
+    fn schema_name() -> String {
+        T::definition().name
+    }
+
+    fn schema() -> anyhow::Result<SchemaDescriptor> {
+        let name = T::definition().name;
+        let mut definitions = MessageDefinitions::new();
+        T::add_definitions(&mut definitions);
+        Ok(definitions.schema(&name)?.into())
+    }
+
+    fn schema_encoding() -> Option<String> {
+        Some("ros1msg".to_owned())
+    }
+
+    fn encode(message: &T) -> anyhow::Result<Vec<u8>> {
+        let mut buffer = vec![];
+        message.write(&mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+/// A ROS 1 `time`, a point in time since the epoch.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Time {
+    /// Seconds.
+    pub sec: u32,
+    /// Nanoseconds.
+    pub nsec: u32,
+}
+
+/// A ROS 1 `duration`, a signed span of time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Duration {
+    /// Seconds.
+    pub sec: i32,
+    /// Nanoseconds.
+    pub nsec: i32,
+}
+
+macro_rules! impl_primitive_field {
+    ($($rust_type:ty => $field_type:ident),* $(,)?) => {
+        $(
+            impl Ros1Field for $rust_type {
+                fn field_type() -> FieldType {
+                    FieldType::$field_type
+                }
+
+                fn write(&self, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+                    buffer.extend(self.to_le_bytes());
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_primitive_field!(
+    i8 => Int8,
+    u8 => UInt8,
+    i16 => Int16,
+    u16 => UInt16,
+    i32 => Int32,
+    u32 => UInt32,
+    i64 => Int64,
+    u64 => UInt64,
+    f32 => Float32,
+    f64 => Float64,
+);
+
+impl Ros1Field for bool {
+    fn field_type() -> FieldType {
+        FieldType::Bool
+    }
+
+    fn write(&self, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+        buffer.push((*self).into());
+        Ok(())
+    }
+}
+
+impl Ros1Field for String {
+    fn field_type() -> FieldType {
+        FieldType::String
+    }
+
+    fn write(&self, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+        write_length(self.len(), "string", buffer)?;
+        buffer.extend(self.as_bytes());
+        Ok(())
+    }
+}
+
+impl Ros1Field for Time {
+    fn field_type() -> FieldType {
+        FieldType::Time
+    }
+
+    fn write(&self, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+        buffer.extend(self.sec.to_le_bytes());
+        buffer.extend(self.nsec.to_le_bytes());
+        Ok(())
+    }
+}
+
+impl Ros1Field for Duration {
+    fn field_type() -> FieldType {
+        FieldType::Duration
+    }
+
+    fn write(&self, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+        buffer.extend(self.sec.to_le_bytes());
+        buffer.extend(self.nsec.to_le_bytes());
+        Ok(())
+    }
+}
+
+impl<T: Ros1Field> Ros1Field for Vec<T> {
+    fn field_type() -> FieldType {
+        FieldType::Array(Box::new(T::field_type()), None)
+    }
+
+    fn add_definitions(definitions: &mut MessageDefinitions) {
+        T::add_definitions(definitions);
+    }
+
+    fn write(&self, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+        write_length(self.len(), "array", buffer)?;
+        self.iter().try_for_each(|element| element.write(buffer))
+    }
+}
+
+impl<T: Ros1Field, const N: usize> Ros1Field for [T; N] {
+    fn field_type() -> FieldType {
+        FieldType::Array(Box::new(T::field_type()), Some(N))
+    }
+
+    fn add_definitions(definitions: &mut MessageDefinitions) {
+        T::add_definitions(definitions);
+    }
+
+    fn write(&self, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+        self.iter().try_for_each(|element| element.write(buffer))
+    }
+}
+
+/// Reads the name of the package from its `package.xml`, falling back to the directory name.
+async fn package_name(path: &Path) -> anyhow::Result<String> {
+    if let Ok(manifest) = tokio::fs::read_to_string(path.join("package.xml")).await {
+        let name = manifest
+            .split_once("<name>")
+            .and_then(|(_, rest)| rest.split_once("</name>"))
+            .map(|(name, _)| name.trim());
+        if let Some(name) = name {
+            return Ok(name.to_owned());
+        }
+    }
+    path.canonicalize()?
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(str::to_owned)
+        .ok_or(anyhow!("Failed to get package name of {}.", path.display()))
+}
+
+/// Writes the length of a string or variable length array.
+fn write_length(length: usize, path: &str, buffer: &mut Vec<u8>) -> anyhow::Result<()> {
+    let length = u32::try_from(length).map_err(|_| anyhow!("{} is too long.", path))?;
+    buffer.extend(length.to_le_bytes());
+    Ok(())
+}
+
+fn int<T: TryFrom<i64> + TryFrom<u64>>(value: &Value, path: &str) -> anyhow::Result<T> {
+    let out_of_range = || anyhow!("{} is out of range for {}.", value, path);
+    if let Some(value) = value.as_u64() {
+        T::try_from(value).map_err(|_| out_of_range())
+    } else if let Some(value) = value.as_i64() {
+        T::try_from(value).map_err(|_| out_of_range())
+    } else {
+        Err(anyhow!("Expected an integer for {}.", path))
+    }
+}
+
+fn float(value: &Value, path: &str) -> anyhow::Result<f64> {
+    value
+        .as_f64()
+        .ok_or(anyhow!("Expected a number for {}.", path))
+}
+
+fn time<'a>(value: &'a Value, path: &str) -> anyhow::Result<(&'a Value, &'a Value)> {
+    let sec = value.get("sec");
+    let nsec = value.get("nsec");
+    sec.zip(nsec).ok_or(anyhow!(
+        "Expected an object with sec and nsec for {}.",
+        path
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Definitions of `geometry_msgs/PoseStamped` as shipped with ROS, comments included.
+    fn pose_stamped() -> MessageDefinitions {
+        let mut definitions = MessageDefinitions::new();
+        definitions
+            .add(
+                "std_msgs/Header",
+                "# Standard metadata for higher-level stamped data types.\n\
+                 uint32 seq\n\
+                 time stamp\n\
+                 string frame_id\n",
+            )
+            .unwrap();
+        definitions
+            .add(
+                "geometry_msgs/PoseStamped",
+                "# A Pose with reference coordinate frame and timestamp\n\
+                 Header header\n\
+                 Pose pose\n",
+            )
+            .unwrap();
+        definitions
+            .add(
+                "geometry_msgs/Pose",
+                "# A representation of pose in free space\n\
+                 Point position\n\
+                 Quaternion orientation\n",
+            )
+            .unwrap();
+        definitions
+            .add("geometry_msgs/Point", "float64 x\nfloat64 y\nfloat64 z\n")
+            .unwrap();
+        definitions
+            .add(
+                "geometry_msgs/Quaternion",
+                "float64 x\nfloat64 y\nfloat64 z\nfloat64 w\n",
+            )
+            .unwrap();
+        definitions
+    }
+
+    #[test]
+    fn schema_lists_dependencies_depth_first() {
+        let expected = format!(
+            "# A Pose with reference coordinate frame and timestamp\n\
+             Header header\n\
+             Pose pose\n\
+             {0}\n\
+             MSG: std_msgs/Header\n\
+             # Standard metadata for higher-level stamped data types.\n\
+             uint32 seq\n\
+             time stamp\n\
+             string frame_id\n\
+             {0}\n\
+             MSG: geometry_msgs/Pose\n\
+             # A representation of pose in free space\n\
+             Point position\n\
+             Quaternion orientation\n\
+             {0}\n\
+             MSG: geometry_msgs/Point\n\
+             float64 x\n\
+             float64 y\n\
+             float64 z\n\
+             {0}\n\
+             MSG: geometry_msgs/Quaternion\n\
+             float64 x\n\
+             float64 y\n\
+             float64 z\n\
+             float64 w",
+            "=".repeat(80)
+        );
+        assert_eq!(
+            pose_stamped().schema("geometry_msgs/PoseStamped").unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn schema_lists_each_dependency_once() {
+        let mut definitions = pose_stamped();
+        definitions
+            .add(
+                "geometry_msgs/Line",
+                "Point start\nPoint[] via\nPoint[2] ends",
+            )
+            .unwrap();
+        assert_eq!(
+            definitions.schema("geometry_msgs/Line").unwrap(),
+            format!(
+                "Point start\nPoint[] via\nPoint[2] ends\n{}\nMSG: geometry_msgs/Point\n\
+                 float64 x\nfloat64 y\nfloat64 z",
+                SEPARATOR
+            )
+        );
+    }
+
+    #[test]
+    fn schema_fails_on_missing_dependency() {
+        let mut definitions = MessageDefinitions::new();
+        definitions.add("demo/Outer", "Inner inner").unwrap();
+        let err = definitions.schema("demo/Outer").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown message type demo/Inner. Used by demo/Outer."
+        );
+    }
+
+    #[test]
+    fn resolves_field_types() {
+        let definition = MessageDefinition::parse(
+            "demo/Types",
+            "Header header\n\
+             Point local\n\
+             geometry_msgs/Point remote\n\
+             std_msgs/Header[] headers\n\
+             byte legacy_byte\n\
+             char legacy_char\n\
+             float32[3] fixed\n\
+             string[] names\n",
+        )
+        .unwrap();
+        let field_types: Vec<_> = definition
+            .fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.field_type.to_string()))
+            .collect();
+        assert_eq!(
+            field_types,
+            [
+                ("header", "std_msgs/Header"),
+                ("local", "demo/Point"),
+                ("remote", "geometry_msgs/Point"),
+                ("headers", "std_msgs/Header[]"),
+                ("legacy_byte", "int8"),
+                ("legacy_char", "uint8"),
+                ("fixed", "float32[3]"),
+                ("names", "string[]"),
+            ]
+            .map(|(name, field_type)| (name, field_type.to_owned()))
+        );
+    }
+
+    #[test]
+    fn skips_constants_and_comments() {
+        let definition = MessageDefinition::parse(
+            "demo/Log",
+            "# Levels\n\
+             uint8 DEBUG=1 # Debug level\n\
+             uint8 INFO = 2\n\
+             string PREFIX=a # not a comment\n\
+             \n\
+             uint8 level  # One of the levels\n\
+             string msg\n",
+        )
+        .unwrap();
+        assert_eq!(
+            definition.fields,
+            [
+                Field {
+                    name: "level".to_owned(),
+                    field_type: FieldType::UInt8,
+                },
+                Field {
+                    name: "msg".to_owned(),
+                    field_type: FieldType::String,
+                },
+            ]
+        );
+        // The schema keeps the constants, clients need them to display the values.
+        let mut definitions = MessageDefinitions::new();
+        definitions.insert(definition);
+        assert!(definitions
+            .schema("demo/Log")
+            .unwrap()
+            .contains("string PREFIX=a # not a comment"));
+    }
+
+    #[test]
+    fn rejects_invalid_definitions() {
+        for (text, error) in [
+            ("uint8", "Line 1 of demo/Bad: Missing field name."),
+            ("uint8 1x", "Line 1 of demo/Bad: Invalid field name 1x."),
+            (
+                "\nint32[x] a",
+                "Line 2 of demo/Bad: Invalid array length x.",
+            ),
+            ("in-valid a", "Line 1 of demo/Bad: Invalid type in-valid."),
+        ] {
+            assert_eq!(
+                MessageDefinition::parse("demo/Bad", text)
+                    .unwrap_err()
+                    .to_string(),
+                error
+            );
+        }
+        assert!(MessageDefinition::parse("Bad", "uint8 a").is_err());
+    }
+
+    #[test]
+    fn serializes_nested_messages_little_endian() {
+        let message = json!({
+            "header": { "seq": 258, "stamp": { "sec": 1, "nsec": 2 }, "frame_id": "map" },
+            "pose": {
+                "position": { "x": 1.0, "y": -2.0, "z": 0.5 },
+                "orientation": { "x": 0.0, "y": 0.0, "z": 0.0, "w": 1.0 },
+            },
+        });
+        let mut expected = vec![];
+        expected.extend([0x02, 0x01, 0x00, 0x00]);
+        expected.extend([1, 0, 0, 0, 2, 0, 0, 0]);
+        expected.extend([3, 0, 0, 0]);
+        expected.extend(b"map");
+        for value in [1.0_f64, -2.0, 0.5, 0.0, 0.0, 0.0, 1.0] {
+            expected.extend(value.to_le_bytes());
+        }
+        assert_eq!(
+            pose_stamped()
+                .serialize("geometry_msgs/PoseStamped", &message)
+                .unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn serializes_arrays_and_primitives() {
+        let mut definitions = pose_stamped();
+        definitions
+            .add(
+                "demo/Sample",
+                "uint8 KIND=3\n\
+                 bool ok\n\
+                 int8 i8\n\
+                 int16 i16\n\
+                 int64 i64\n\
+                 float32 f32\n\
+                 duration elapsed\n\
+                 uint16[] counts\n\
+                 int32[2] pair\n\
+                 string[] names\n\
+                 geometry_msgs/Point[] points\n\
+                 uint8[] empty\n",
+            )
+            .unwrap();
+        let message = json!({
+            "ok": true,
+            "i8": -1,
+            "i16": -2,
+            "i64": 0x0102_0304_0506_0708_i64,
+            "f32": 1.5,
+            "elapsed": { "sec": -1, "nsec": 5 },
+            "counts": [1, 0x1234],
+            "pair": [-1, 2],
+            "names": ["a", "bc"],
+            "points": [{ "x": 1.0, "y": 2.0, "z": 3.0 }],
+            "empty": [],
+        });
+        let mut expected = vec![1, 0xff, 0xfe, 0xff];
+        expected.extend([0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01]);
+        expected.extend(1.5_f32.to_le_bytes());
+        expected.extend([0xff, 0xff, 0xff, 0xff, 5, 0, 0, 0]);
+        expected.extend([2, 0, 0, 0, 0x01, 0x00, 0x34, 0x12]);
+        expected.extend([0xff, 0xff, 0xff, 0xff, 2, 0, 0, 0]);
+        expected.extend([2, 0, 0, 0, 1, 0, 0, 0, b'a', 2, 0, 0, 0, b'b', b'c']);
+        expected.extend([1, 0, 0, 0]);
+        for value in [1.0_f64, 2.0, 3.0] {
+            expected.extend(value.to_le_bytes());
+        }
+        expected.extend([0, 0, 0, 0]);
+        assert_eq!(
+            definitions.serialize("demo/Sample", &message).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn reports_invalid_values_with_their_path() {
+        let mut definitions = pose_stamped();
+        definitions
+            .add("demo/Scan", "uint8[2] ids\nstd_msgs/Header[] headers")
+            .unwrap();
+        for (message, error) in [
+            (
+                json!({ "ids": [1], "headers": [] }),
+                "Expected 2 elements for demo/Scan.ids, got 1.",
+            ),
+            (
+                json!({ "ids": [1, 256], "headers": [] }),
+                "256 is out of range for demo/Scan.ids[1].",
+            ),
+            (
+                json!({ "ids": [1, 2], "headers": [{ "seq": 0, "stamp": { "sec": 0 } }] }),
+                "Expected an object with sec and nsec for demo/Scan.headers[0].stamp.",
+            ),
+            (json!({ "ids": [1, 2] }), "Missing field demo/Scan.headers."),
+        ] {
+            assert_eq!(
+                definitions
+                    .serialize("demo/Scan", &message)
+                    .unwrap_err()
+                    .to_string(),
+                error
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn loads_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        let package = dir.path().join("demo_msgs");
+        tokio::fs::create_dir_all(package.join("msg"))
+            .await
+            .unwrap();
+        tokio::fs::write(package.join("msg/Status.msg"), "Header header\nuint8 level")
+            .await
+            .unwrap();
+        tokio::fs::write(package.join("msg/README.md"), "Not a message.")
+            .await
+            .unwrap();
+
+        let mut definitions = MessageDefinitions::new();
+        definitions.load_package(&package).await.unwrap();
+        assert!(definitions.get("demo_msgs/Status").is_some());
+        assert!(definitions.get("demo_msgs/README").is_none());
+
+        // The name in package.xml takes precedence over the name of the directory.
+        tokio::fs::write(
+            package.join("package.xml"),
+            "<package format=\"2\">\n  <name> renamed_msgs </name>\n</package>\n",
+        )
+        .await
+        .unwrap();
+        definitions.load_package(&package).await.unwrap();
+        assert_eq!(
+            definitions.get("renamed_msgs/Status").unwrap().fields[0].field_type,
+            FieldType::Message("std_msgs/Header".to_owned())
+        );
+    }
+}
@@ -0,0 +1,329 @@
+//! Services that clients can call (`services` capability).
+
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{Cursor, Write},
+    mem::size_of,
+    sync::{atomic::AtomicU32, Arc},
+};
+
+use futures_util::future::BoxFuture;
+use tokio::sync::{RwLock, Semaphore};
+use uuid::Uuid;
+use warp::ws::Message;
+
+use crate::{
+    protocol_types::*, queue::ClientQueue, ClientState, ConnectionGraph, Identity, SchemaDescriptor,
+};
+
+/// Number of service calls of a client that are handled at once. Further calls are rejected
+/// until a call finished.
+pub(crate) const MAX_CONCURRENT_CALLS: usize = 16;
+
+/// Describes the request or response message of a service.
+pub struct ServiceSchema {
+    encoding: String,
+    schema_name: String,
+    schema: String,
+    schema_encoding: String,
+}
+
+impl ServiceSchema {
+    /// Creates a new service message description.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoding` - Message encoding, e.g. `json` or `ros1`.
+    /// * `schema_name` - Name of the schema.
+    /// * `schema` - Schema describing the message format.
+    /// * `schema_encoding` - Encoding of the schema, e.g. `jsonschema` or `ros1msg`.
+    pub fn new<S: Into<SchemaDescriptor>>(
+        encoding: &str,
+        schema_name: &str,
+        schema: S,
+        schema_encoding: &str,
+    ) -> Self {
+        Self {
+            encoding: encoding.to_owned(),
+            schema_name: schema_name.to_owned(),
+            schema: schema.into().0,
+            schema_encoding: schema_encoding.to_owned(),
+        }
+    }
+}
+
+impl From<ServiceSchema> for ServiceMessageSchema {
+    fn from(schema: ServiceSchema) -> Self {
+        Self {
+            encoding: schema.encoding,
+            schema_name: schema.schema_name,
+            schema_encoding: schema.schema_encoding,
+            schema: schema.schema,
+        }
+    }
+}
+
+/// A call of a service by a client.
+#[derive(Clone, Debug)]
+pub struct ServiceRequest {
+    /// ID of the calling client.
+    pub client_id: Uuid,
+    /// Identity of the calling client, if an authenticator is set.
+    pub identity: Option<Identity>,
+    /// ID of this call, unique for the calling client.
+    pub call_id: u32,
+    /// Encoding of the request data. The response must use the same encoding.
+    pub encoding: String,
+    /// Encoded request data.
+    pub data: Vec<u8>,
+}
+
+pub(crate) type ServiceHandler =
+    Arc<dyn Fn(ServiceRequest) -> BoxFuture<'static, anyhow::Result<Vec<u8>>> + Send + Sync>;
+
+pub(crate) struct ServiceEntry {
+    pub(crate) service_message: ServerServiceMessage,
+    pub(crate) handler: ServiceHandler,
+}
+
+impl fmt::Debug for ServiceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServiceEntry")
+            .field("service_message", &self.service_message)
+            .finish_non_exhaustive()
+    }
+}
+
+type Services = RwLock<HashMap<ServiceId, ServiceEntry>>;
+
+#[derive(Debug, Default)]
+pub(crate) struct ServiceState {
+    pub(crate) next_service_id: AtomicU32,
+    pub(crate) services: Services,
+}
+
+/// Represents a service that is advertised to the clients. The service is unadvertised when this
+/// handle is dropped.
+#[derive(Debug)]
+pub struct AdvertisedService {
+    pub(crate) id: ServiceId,
+    pub(crate) name: String,
+
+    pub(crate) clients: Arc<ClientState>,
+    pub(crate) services: Arc<ServiceState>,
+    pub(crate) connection_graph: ConnectionGraph,
+    pub(crate) unadvertised: bool,
+}
+
+impl AdvertisedService {
+    /// Name of the service.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Unadvertises this service to all clients.
+    pub async fn unadvertise(mut self) -> anyhow::Result<()> {
+        self.unadvertised = true;
+        unadvertise_service(
+            self.id,
+            &self.clients,
+            &self.services,
+            &self.connection_graph,
+        )
+        .await
+    }
+}
+
+impl Drop for AdvertisedService {
+    fn drop(&mut self) {
+        if self.unadvertised {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            log::warn!("Cannot unadvertise service {}: no runtime.", self.name);
+            return;
+        };
+        let service_id = self.id;
+        let clients = self.clients.clone();
+        let services = self.services.clone();
+        let connection_graph = self.connection_graph.clone();
+        runtime.spawn(async move {
+            if let Err(e) =
+                unadvertise_service(service_id, &clients, &services, &connection_graph).await
+            {
+                log::error!("Failed to unadvertise service: {}", e);
+            }
+        });
+    }
+}
+
+async fn unadvertise_service(
+    service_id: ServiceId,
+    clients: &ClientState,
+    services: &ServiceState,
+    connection_graph: &ConnectionGraph,
+) -> anyhow::Result<()> {
+    services.services.write().await.remove(&service_id);
+
+    let message = Message::text(serde_json::to_string(
+        &ServerMessage::UnadvertiseServices {
+            service_ids: vec![service_id],
+        },
+    )?);
+    for client in clients.clients.read().await.values() {
+        client.broadcast(message.clone());
+    }
+    connection_graph.update().await
+}
+
+fn build_service_call_response(
+    service_id: ServiceId,
+    call_id: u32,
+    encoding: &str,
+    data: &[u8],
+) -> anyhow::Result<Message> {
+    let mut buffer = vec![0; size_of::<u8>() + 3 * size_of::<u32>() + encoding.len() + data.len()];
+    {
+        let mut w = Cursor::new(&mut buffer);
+        // Write op code for the "Service Call Response" type.
+        w.write_all(&3_u8.to_le_bytes())?;
+        w.write_all(&service_id.to_le_bytes())?;
+        w.write_all(&call_id.to_le_bytes())?;
+        w.write_all(&(encoding.len() as u32).to_le_bytes())?;
+        w.write_all(encoding.as_bytes())?;
+        w.write_all(data)?;
+    }
+    Ok(Message::binary(buffer))
+}
+
+/// Runs a service call in the background and replies to the calling client with either the
+/// response or a failure message.
+///
+/// Each call holds a permit of `calls`, the client's calls in flight. The call is rejected if no
+/// permit is left.
+pub(crate) async fn call_service(
+    tx: &Arc<ClientQueue>,
+    calls: &Arc<Semaphore>,
+    services: &ServiceState,
+    service_id: ServiceId,
+    request: ServiceRequest,
+) -> anyhow::Result<()> {
+    let handler = services
+        .services
+        .read()
+        .await
+        .get(&service_id)
+        .map(|service| service.handler.clone());
+    let tx = tx.clone();
+    let call_id = request.call_id;
+
+    let Some(handler) = handler else {
+        return reject_call(
+            &tx,
+            service_id,
+            call_id,
+            &format!("Unknown service {}.", service_id),
+        );
+    };
+    let Ok(permit) = calls.clone().try_acquire_owned() else {
+        log::debug!("Rejecting service call {}: too many in flight.", call_id);
+        return reject_call(
+            &tx,
+            service_id,
+            call_id,
+            &format!(
+                "Too many service calls, at most {} are handled at once.",
+                MAX_CONCURRENT_CALLS
+            ),
+        );
+    };
+
+    tokio::spawn(async move {
+        let _permit = permit;
+        let encoding = request.encoding.clone();
+        let reply = match handler(request).await {
+            Ok(data) => build_service_call_response(service_id, call_id, &encoding, &data),
+            Err(err) => serde_json::to_string(&ServerMessage::ServiceCallFailure {
+                service_id,
+                call_id,
+                message: err.to_string(),
+            })
+            .map(Message::text)
+            .map_err(anyhow::Error::from),
+        };
+        let result = match reply {
+            Ok(message) => tx.reply(message),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            log::error!("Failed to reply to service call {}: {}.", call_id, err);
+        }
+    });
+    Ok(())
+}
+
+/// Answers a service call with a failure message.
+fn reject_call(
+    tx: &ClientQueue,
+    service_id: ServiceId,
+    call_id: u32,
+    message: &str,
+) -> anyhow::Result<()> {
+    let failure = ServerMessage::ServiceCallFailure {
+        service_id,
+        call_id,
+        message: message.to_owned(),
+    };
+    tx.reply(Message::text(serde_json::to_string(&failure)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{future, FutureExt};
+
+    use super::*;
+
+    fn request(call_id: u32) -> ServiceRequest {
+        ServiceRequest {
+            client_id: Uuid::nil(),
+            identity: None,
+            call_id,
+            encoding: "json".to_owned(),
+            data: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn limits_calls_in_flight() {
+        let tx = Arc::new(ClientQueue::new(16, Default::default()));
+        let calls = Arc::new(Semaphore::new(MAX_CONCURRENT_CALLS));
+        let services = ServiceState::default();
+        let handler: ServiceHandler = Arc::new(|_| future::pending().boxed());
+        services.services.write().await.insert(
+            0,
+            ServiceEntry {
+                service_message: ServerServiceMessage {
+                    id: 0,
+                    name: "/wait".to_owned(),
+                    service_type: "Empty".to_owned(),
+                    request: ServiceSchema::new("json", "Empty", "{}", "jsonschema").into(),
+                    response: ServiceSchema::new("json", "Empty", "{}", "jsonschema").into(),
+                },
+                handler,
+            },
+        );
+        for call_id in 0..=MAX_CONCURRENT_CALLS as u32 {
+            call_service(&tx, &calls, &services, 0, request(call_id))
+                .await
+                .unwrap();
+        }
+        assert_eq!(calls.available_permits(), 0);
+
+        // Only the call beyond the limit is answered, with a failure.
+        let (message, _) = tx.pop().await.unwrap();
+        let failure: serde_json::Value = serde_json::from_str(message.to_str().unwrap()).unwrap();
+        assert_eq!(failure["op"], "serviceCallFailure");
+        assert_eq!(failure["callId"], MAX_CONCURRENT_CALLS);
+    }
+}
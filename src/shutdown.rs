@@ -0,0 +1,123 @@
+//! Graceful shutdown of the server and bookkeeping of the client connections.
+
+use std::{
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::sync::Notify;
+use warp::ws::Message;
+
+use crate::{protocol_types::ServerMessage, queue::ClientQueue, StatusLevel};
+
+/// Time clients get to receive their remaining messages after the server stopped, before their
+/// connections are closed forcefully.
+pub(crate) const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default)]
+pub(crate) struct ShutdownState {
+    /// Set while the server shuts down. Clients that connect meanwhile are disconnected right
+    /// away.
+    shutting_down: AtomicBool,
+    /// Set while the clients are disconnected forcefully.
+    aborted: AtomicBool,
+    /// Number of client connections that did not finish yet.
+    connections: AtomicUsize,
+    /// Wakes everyone waiting for all connections to finish.
+    connections_finished: Notify,
+    /// Wakes the connections when they are disconnected forcefully.
+    connections_aborted: Notify,
+}
+
+impl ShutdownState {
+    pub(crate) fn begin(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+    }
+
+    pub(crate) fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn abort(&self) {
+        self.aborted.store(true, Ordering::SeqCst);
+        self.connections_aborted.notify_waiters();
+    }
+
+    pub(crate) fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+
+    /// Leaves the shutdown once all connections finished, so that the server can be served
+    /// again.
+    pub(crate) fn reset(&self) {
+        self.shutting_down.store(false, Ordering::SeqCst);
+        self.aborted.store(false, Ordering::SeqCst);
+    }
+
+    /// Waits until the connections are disconnected forcefully.
+    pub(crate) async fn aborted(&self) {
+        let mut connections_aborted = pin!(self.connections_aborted.notified());
+        connections_aborted.as_mut().enable();
+        if self.is_aborted() {
+            return;
+        }
+        connections_aborted.await;
+    }
+
+    /// Counts a client connection until the returned guard is dropped.
+    pub(crate) fn track_connection(self: &Arc<Self>) -> ConnectionGuard {
+        self.connections.fetch_add(1, Ordering::SeqCst);
+        ConnectionGuard {
+            state: self.clone(),
+        }
+    }
+
+    /// Waits until all client connections finished.
+    pub(crate) async fn connections_finished(&self) {
+        loop {
+            let mut connections_finished = pin!(self.connections_finished.notified());
+            connections_finished.as_mut().enable();
+            if self.connections.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            connections_finished.await;
+        }
+    }
+}
+
+/// Marks a client connection as running while it is alive.
+#[derive(Debug)]
+pub(crate) struct ConnectionGuard {
+    state: Arc<ShutdownState>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        if self.state.connections.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.state.connections_finished.notify_waiters();
+        }
+    }
+}
+
+/// Tells a client that the server shuts down and stops queueing messages for it. The client's
+/// connection is closed once the already queued control messages are written.
+pub(crate) fn finish_client(tx: &ClientQueue) {
+    let message = ServerMessage::Status {
+        level: StatusLevel::Info,
+        message: "Server is shutting down.".to_owned(),
+        id: None,
+    };
+    match serde_json::to_string(&message) {
+        Ok(message) => {
+            if let Err(err) = tx.send(Message::text(message)) {
+                log::debug!("Failed to send shutdown status: {}.", err);
+            }
+        }
+        Err(err) => log::error!("Failed to serialize shutdown status: {}.", err),
+    }
+    tx.finish();
+}
@@ -0,0 +1,88 @@
+//! Delivery statistics of clients and channels.
+
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use uuid::Uuid;
+
+/// Number of messages and bytes that were sent to clients, dropped or are waiting to be sent.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DeliveryStats {
+    /// Messages written to the WebSockets.
+    pub sent_messages: u64,
+    /// Bytes written to the WebSockets.
+    pub sent_bytes: u64,
+    /// Messages dropped because clients did not keep up, unsubscribed or disconnected.
+    pub dropped_messages: u64,
+    /// Bytes of the dropped messages.
+    pub dropped_bytes: u64,
+    /// Messages currently waiting to be sent.
+    pub queued_messages: u64,
+    /// Bytes of the messages currently waiting to be sent.
+    pub queued_bytes: u64,
+}
+
+/// Delivery statistics of a channel, summed up over all clients.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelStats {
+    /// Name of the topic of the channel.
+    pub topic: String,
+    /// Messages of the channel.
+    pub delivery: DeliveryStats,
+}
+
+/// Snapshot of the delivery statistics, created by [`crate::FoxgloveWebSocket::stats`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ServerStats {
+    /// Statistics of the connected clients by client ID, counting all messages sent to them
+    /// except the ones sent right after connecting, see [`crate::FoxgloveWebSocket::stats`].
+    pub clients: HashMap<Uuid, DeliveryStats>,
+    /// Statistics of the advertised channels by channel ID.
+    pub channels: HashMap<usize, ChannelStats>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct DeliveryCounters {
+    sent_messages: AtomicU64,
+    sent_bytes: AtomicU64,
+    dropped_messages: AtomicU64,
+    dropped_bytes: AtomicU64,
+    queued_messages: AtomicU64,
+    queued_bytes: AtomicU64,
+}
+
+impl DeliveryCounters {
+    pub(crate) fn queued(&self, bytes: usize) {
+        self.queued_messages.fetch_add(1, Ordering::Relaxed);
+        self.queued_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn dequeued(&self, bytes: usize) {
+        self.queued_messages.fetch_sub(1, Ordering::Relaxed);
+        self.queued_bytes.fetch_sub(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn sent(&self, bytes: usize) {
+        self.sent_messages.fetch_add(1, Ordering::Relaxed);
+        self.sent_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn dropped(&self, bytes: usize) {
+        self.dropped_messages.fetch_add(1, Ordering::Relaxed);
+        self.dropped_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> DeliveryStats {
+        DeliveryStats {
+            sent_messages: self.sent_messages.load(Ordering::Relaxed),
+            sent_bytes: self.sent_bytes.load(Ordering::Relaxed),
+            dropped_messages: self.dropped_messages.load(Ordering::Relaxed),
+            dropped_bytes: self.dropped_bytes.load(Ordering::Relaxed),
+            queued_messages: self.queued_messages.load(Ordering::Relaxed),
+            queued_bytes: self.queued_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
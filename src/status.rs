@@ -0,0 +1,132 @@
+//! Status messages shown to the user in Foxglove.
+
+use std::{collections::HashMap, sync::Arc};
+
+use serde::{Serialize, Serializer};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+use warp::ws::Message;
+
+use crate::{protocol_types::*, ClientState};
+
+/// Severity of a status message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StatusLevel {
+    /// Informational message.
+    Info,
+    /// Something is not working as expected.
+    Warning,
+    /// Something failed.
+    Error,
+}
+
+impl Serialize for StatusLevel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(match self {
+            StatusLevel::Info => 0,
+            StatusLevel::Warning => 1,
+            StatusLevel::Error => 2,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct StatusState {
+    /// Statuses shown to all clients, including clients that connect later on.
+    pub(crate) active: RwLock<HashMap<String, ServerMessage>>,
+}
+
+/// Represents a status shown in Foxglove. The status is removed when this handle is cleared or
+/// dropped.
+#[derive(Debug)]
+pub struct StatusHandle {
+    pub(crate) id: String,
+    /// Client the status is shown to or `None` if it is shown to all clients.
+    pub(crate) client_id: Option<Uuid>,
+
+    pub(crate) clients: Arc<ClientState>,
+    pub(crate) statuses: Arc<StatusState>,
+    pub(crate) cleared: bool,
+}
+
+impl StatusHandle {
+    /// ID of the status.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Removes the status from the clients.
+    pub async fn clear(mut self) -> anyhow::Result<()> {
+        self.cleared = true;
+        remove_status(&self.id, self.client_id, &self.clients, &self.statuses).await
+    }
+}
+
+impl Drop for StatusHandle {
+    fn drop(&mut self) {
+        if self.cleared {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            log::warn!("Cannot remove status {}: no runtime.", self.id);
+            return;
+        };
+        let id = self.id.clone();
+        let client_id = self.client_id;
+        let clients = self.clients.clone();
+        let statuses = self.statuses.clone();
+        runtime.spawn(async move {
+            if let Err(e) = remove_status(&id, client_id, &clients, &statuses).await {
+                log::error!("Failed to remove status: {}", e);
+            }
+        });
+    }
+}
+
+async fn remove_status(
+    id: &str,
+    client_id: Option<Uuid>,
+    clients: &ClientState,
+    statuses: &StatusState,
+) -> anyhow::Result<()> {
+    statuses.active.write().await.remove(id);
+    if let Some(client_id) = client_id {
+        // The status went away together with its client.
+        if !clients.clients.read().await.contains_key(&client_id) {
+            return Ok(());
+        }
+    }
+    send_status_message(
+        &ServerMessage::RemoveStatus {
+            status_ids: vec![id.to_owned()],
+        },
+        client_id.as_ref(),
+        clients,
+    )
+    .await
+}
+
+/// Sends a status related message to a single client or to all clients if `client_id` is `None`.
+pub(crate) async fn send_status_message(
+    message: &ServerMessage,
+    client_id: Option<&Uuid>,
+    clients: &ClientState,
+) -> anyhow::Result<()> {
+    let message = Message::text(serde_json::to_string(message)?);
+    let clients = clients.clients.read().await;
+    match client_id {
+        Some(client_id) => {
+            clients
+                .get(client_id)
+                .ok_or(anyhow::anyhow!("Unknown client {}.", client_id))?
+                .tx
+                .send(message)?;
+        }
+        None => {
+            for client in clients.values() {
+                client.broadcast(message.clone());
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,76 @@
+//! Server time broadcasting (`time` capability).
+
+use std::{
+    io::{Cursor, Write},
+    mem::size_of,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::{sync::RwLock, task::JoinHandle};
+use warp::ws::Message;
+
+/// Source of the time that is broadcast to clients, e.g. the clock of a simulation.
+pub trait Clock: Send + Sync + 'static {
+    /// Current time in nanoseconds.
+    fn now_ns(&self) -> u64;
+}
+
+/// Wall clock time as nanoseconds since the Unix epoch.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_ns(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64
+    }
+}
+
+impl<F> Clock for F
+where
+    F: Fn() -> u64 + Send + Sync + 'static,
+{
+    fn now_ns(&self) -> u64 {
+        self()
+    }
+}
+
+/// Handle of a running clock that broadcasts its time to all clients. The clock stops when this
+/// handle is dropped.
+#[derive(Debug)]
+pub struct ClockHandle {
+    pub(crate) task: JoinHandle<()>,
+    pub(crate) period: Duration,
+}
+
+impl ClockHandle {
+    /// Time between two broadcasts.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+}
+
+impl Drop for ClockHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct TimeState {
+    /// Last broadcast time. The time capability is only announced once time was broadcast.
+    pub(crate) last_time_ns: RwLock<Option<u64>>,
+}
+
+pub(crate) fn build_time_message(timestamp_ns: u64) -> anyhow::Result<Message> {
+    let mut buffer = vec![0; size_of::<u8>() + size_of::<u64>()];
+    {
+        let mut w = Cursor::new(&mut buffer);
+        // Write op code for the "Time" type.
+        w.write_all(&2_u8.to_le_bytes())?;
+        w.write_all(&timestamp_ns.to_le_bytes())?;
+    }
+    Ok(Message::binary(buffer))
+}
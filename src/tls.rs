@@ -0,0 +1,229 @@
+//! Serving clients over TLS (`wss://`), enabled with the `tls` feature.
+
+use std::{
+    fmt,
+    future::Future,
+    io::Cursor,
+    net::{SocketAddr, TcpListener as StdTcpListener},
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::anyhow;
+use futures_util::future;
+use tokio::net::TcpListener;
+use tokio_rustls::{
+    rustls::{
+        crypto::ring,
+        server::{ClientHello, ResolvesServerCert},
+        sign::CertifiedKey,
+        ServerConfig,
+    },
+    TlsAcceptor,
+};
+
+use crate::FoxgloveWebSocket;
+
+/// Certificate and private key of a server serving clients over TLS.
+///
+/// The certificate can be replaced while the server is running, e.g. when it was renewed. New
+/// connections use the new certificate right away, established connections are kept.
+///
+/// # Example
+///
+/// ```
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// use foxglove_ws::{FoxgloveWebSocket, TlsConfig};
+///
+/// // A self-signed certificate, e.g. for tests.
+/// let certificate = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()])?;
+/// let tls = TlsConfig::from_pem(
+///     certificate.cert.pem().as_bytes(),
+///     certificate.key_pair.serialize_pem().as_bytes(),
+/// )?;
+///
+/// let server = FoxgloveWebSocket::new("robot");
+/// let (addr, serving) =
+///     server.serve_tls_with_shutdown(([127, 0, 0, 1], 0), tls.clone(), async {})?;
+/// println!("Listening on wss://{}.", addr);
+///
+/// // Swap in a renewed certificate without restarting the server.
+/// let renewed = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()])?;
+/// tls.reload_from_pem(
+///     renewed.cert.pem().as_bytes(),
+///     renewed.key_pair.serialize_pem().as_bytes(),
+/// )?;
+/// serving.await;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    certificate: Arc<CertificateResolver>,
+    server_config: Arc<ServerConfig>,
+}
+
+impl TlsConfig {
+    /// Creates a configuration from a PEM encoded certificate chain and private key.
+    ///
+    /// # Arguments
+    ///
+    /// * `certificate` - Certificate chain, starting with the server's certificate.
+    /// * `private_key` - Private key of the server's certificate.
+    pub fn from_pem(certificate: &[u8], private_key: &[u8]) -> anyhow::Result<Self> {
+        let certificate = Arc::new(CertificateResolver {
+            current: RwLock::new(parse_pem(certificate, private_key)?),
+        });
+        let mut server_config =
+            ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()?
+                .with_no_client_auth()
+                .with_cert_resolver(certificate.clone());
+        server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+        Ok(Self {
+            certificate,
+            server_config: Arc::new(server_config),
+        })
+    }
+
+    /// Creates a configuration from the PEM files of a certificate chain and private key.
+    ///
+    /// # Arguments
+    ///
+    /// * `certificate_path` - File of the certificate chain, starting with the server's
+    ///   certificate.
+    /// * `private_key_path` - File of the private key of the server's certificate.
+    pub async fn from_pem_files(
+        certificate_path: impl AsRef<Path>,
+        private_key_path: impl AsRef<Path>,
+    ) -> anyhow::Result<Self> {
+        let (certificate, private_key) =
+            read_pem_files(certificate_path.as_ref(), private_key_path.as_ref()).await?;
+        Self::from_pem(&certificate, &private_key)
+    }
+
+    /// Replaces the certificate chain and private key. Keeps the current ones if the new ones
+    /// are invalid.
+    pub fn reload_from_pem(&self, certificate: &[u8], private_key: &[u8]) -> anyhow::Result<()> {
+        let certified_key = parse_pem(certificate, private_key)?;
+        *self.certificate.current.write().unwrap() = certified_key;
+        Ok(())
+    }
+
+    /// Replaces the certificate chain and private key with the content of PEM files. Keeps the
+    /// current ones if the new ones are invalid.
+    pub async fn reload_from_pem_files(
+        &self,
+        certificate_path: impl AsRef<Path>,
+        private_key_path: impl AsRef<Path>,
+    ) -> anyhow::Result<()> {
+        let (certificate, private_key) =
+            read_pem_files(certificate_path.as_ref(), private_key_path.as_ref()).await?;
+        self.reload_from_pem(&certificate, &private_key)
+    }
+}
+
+async fn read_pem_files(
+    certificate_path: &Path,
+    private_key_path: &Path,
+) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+    let certificate = tokio::fs::read(certificate_path).await.map_err(|err| {
+        anyhow!(
+            "Failed to read certificate {}: {}.",
+            certificate_path.display(),
+            err
+        )
+    })?;
+    let private_key = tokio::fs::read(private_key_path).await.map_err(|err| {
+        anyhow!(
+            "Failed to read private key {}: {}.",
+            private_key_path.display(),
+            err
+        )
+    })?;
+    Ok((certificate, private_key))
+}
+
+fn parse_pem(certificate: &[u8], private_key: &[u8]) -> anyhow::Result<Arc<CertifiedKey>> {
+    let certificates = rustls_pemfile::certs(&mut Cursor::new(certificate))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| anyhow!("Invalid certificate: {}.", err))?;
+    if certificates.is_empty() {
+        return Err(anyhow!("No certificate found."));
+    }
+    let private_key = rustls_pemfile::private_key(&mut Cursor::new(private_key))
+        .map_err(|err| anyhow!("Invalid private key: {}.", err))?
+        .ok_or(anyhow!("No private key found."))?;
+    let signing_key = ring::sign::any_supported_type(&private_key)
+        .map_err(|err| anyhow!("Unsupported private key: {}.", err))?;
+    Ok(Arc::new(CertifiedKey::new(certificates, signing_key)))
+}
+
+/// Hands out the current certificate for each handshake, so that it can be swapped at any time.
+struct CertificateResolver {
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl fmt::Debug for CertificateResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CertificateResolver")
+            .finish_non_exhaustive()
+    }
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+impl FoxgloveWebSocket {
+    /// Listens on `addr` for TLS connections and returns the address actually listened on
+    /// together with the future serving connecting clients. The future never finishes.
+    ///
+    /// Has to be called from within a Tokio runtime.
+    ///
+    /// # Arguments
+    ///
+    /// `addr` -- Address to listen on.
+    /// `tls` -- Certificate of the server.
+    pub fn try_bind_tls(
+        &self,
+        addr: impl Into<SocketAddr>,
+        tls: TlsConfig,
+    ) -> anyhow::Result<(SocketAddr, impl Future<Output = ()> + Send + 'static)> {
+        self.serve_tls_with_shutdown(addr, tls, future::pending())
+    }
+
+    /// Listens on `addr` for TLS connections and returns the address actually listened on
+    /// together with the future serving connecting clients until `signal` finishes.
+    ///
+    /// Shuts down like [`FoxgloveWebSocket::serve_with_shutdown`]. Has to be called from within
+    /// a Tokio runtime.
+    ///
+    /// # Arguments
+    ///
+    /// `addr` -- Address to listen on.
+    /// `tls` -- Certificate of the server.
+    /// `signal` -- Future that triggers the shutdown when it finishes.
+    pub fn serve_tls_with_shutdown(
+        &self,
+        addr: impl Into<SocketAddr>,
+        tls: TlsConfig,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> anyhow::Result<(SocketAddr, impl Future<Output = ()> + Send + 'static)> {
+        let listener = StdTcpListener::bind(addr.into())?;
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        let addr = listener.local_addr()?;
+        let server = self.clone();
+        Ok((addr, async move {
+            let acceptor = TlsAcceptor::from(tls.server_config);
+            server
+                .accept_connections(listener, signal, |stream| acceptor.accept(stream))
+                .await;
+            server.shutdown_clients().await;
+        }))
+    }
+}
@@ -0,0 +1,69 @@
+//! Authenticating connecting clients.
+
+mod common;
+
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Error};
+use warp::http::StatusCode;
+
+use foxglove_ws::{AuthRejection, FoxgloveWebSocket, Identity};
+
+use common::{connect, next_op};
+
+fn server() -> FoxgloveWebSocket {
+    FoxgloveWebSocket::builder()
+        .authenticator(|request| async move {
+            match request.token() {
+                Some("operator-secret") => Ok(Identity::new("operator").with_role("operator")),
+                Some(_) => Err(AuthRejection::forbidden("Invalid token.")),
+                None => Err(AuthRejection::unauthorized("Missing token.")),
+            }
+        })
+        .build()
+}
+
+#[tokio::test]
+async fn hands_the_identity_of_accepted_clients_out() {
+    let server = server();
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    let serving = tokio::spawn(serving);
+
+    let mut ws = connect(addr, "/?token=operator-secret").await;
+    next_op(&mut ws, "serverInfo").await;
+    let client_id = *server.stats().await.clients.keys().next().unwrap();
+    assert_eq!(
+        server.client_identity(&client_id).await,
+        Some(Identity::new("operator").with_role("operator"))
+    );
+
+    serving.abort();
+}
+
+#[tokio::test]
+async fn answers_rejected_clients_with_the_status_of_the_rejection() {
+    let server = server();
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    let serving = tokio::spawn(serving);
+
+    for (path, status) in [
+        ("/?token=wrong", StatusCode::FORBIDDEN),
+        ("/", StatusCode::UNAUTHORIZED),
+    ] {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut request = format!("ws://{}{}", addr, path)
+            .into_client_request()
+            .unwrap();
+        request.headers_mut().insert(
+            "Sec-WebSocket-Protocol",
+            "foxglove.websocket.v1".parse().unwrap(),
+        );
+        match tokio_tungstenite::client_async(request, stream).await {
+            Err(Error::Http(response)) => assert_eq!(response.status().as_u16(), status.as_u16()),
+            Err(err) => panic!("Expected an HTTP error, got {}.", err),
+            Ok(_) => panic!("Expected {} to be rejected.", path),
+        }
+    }
+    assert!(server.stats().await.clients.is_empty());
+
+    serving.abort();
+}
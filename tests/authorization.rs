@@ -0,0 +1,148 @@
+//! Requests of clients denied by the authorization policy.
+
+mod common;
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use futures_util::{FutureExt, StreamExt};
+use serde_json::json;
+use tokio_tungstenite::tungstenite::Message;
+
+use foxglove_ws::{
+    FoxgloveWebSocket, Identity, InMemoryAssetProvider, Operation, ParameterValue, ServiceSchema,
+};
+
+use common::{connect, next_message, next_op, send_binary, send_json};
+
+/// Denies everything but advertising `/allowed`.
+fn policy(_identity: Option<&Identity>, operation: &Operation<'_>) -> bool {
+    matches!(operation, Operation::Advertise { topic: "/allowed" })
+}
+
+async fn expect_denied<S>(ws: &mut tokio_tungstenite::WebSocketStream<S>, operation: &str)
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let status = next_op(ws, "status").await;
+    assert_eq!(
+        status,
+        json!({
+            "op": "status",
+            "level": 2,
+            "message": format!("Permission denied: {}.", operation),
+        })
+    );
+}
+
+#[tokio::test]
+async fn denied_requests_have_no_effect() {
+    let server = FoxgloveWebSocket::builder()
+        .authorization_policy(policy)
+        .build();
+    let channel = server
+        .create_publisher("/topic", "json", "Type", "{}", Some("jsonschema"), false)
+        .await
+        .unwrap();
+    let called = Arc::new(AtomicBool::new(false));
+    let schema = || ServiceSchema::new("json", "Empty", "{}", "jsonschema");
+    let _service = server
+        .advertise_service("/reset", "Empty", schema(), schema(), {
+            let called = called.clone();
+            move |_| {
+                called.store(true, Ordering::Relaxed);
+                async { Ok(Vec::new()) }
+            }
+        })
+        .await
+        .unwrap();
+    server.parameters.set("speed", 1).await.unwrap();
+    let assets = InMemoryAssetProvider::new();
+    assets.insert("package://robot/mesh.stl", "solid").await;
+    server.set_asset_provider(assets).await;
+    let mut denied_messages = server.subscribe_client_messages("/cmd_vel").await;
+    let mut allowed_messages = server.subscribe_client_messages("/allowed").await;
+
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    let serving = tokio::spawn(serving);
+    let mut ws = connect(addr, "/").await;
+    next_op(&mut ws, "serverInfo").await;
+    let advertise = next_op(&mut ws, "advertise").await;
+    let channel_id = advertise["channels"][0]["id"].clone();
+
+    send_json(
+        &mut ws,
+        json!({"op": "subscribe", "subscriptions": [{"id": 1, "channelId": channel_id}]}),
+    )
+    .await;
+    expect_denied(&mut ws, "subscribe to /topic").await;
+    channel.send(0, b"{}").await.unwrap();
+    let stats = server.stats().await;
+    assert_eq!(
+        stats.channels.values().next().unwrap().delivery,
+        Default::default()
+    );
+
+    send_json(
+        &mut ws,
+        json!({"op": "setParameters", "parameters": [{"name": "speed", "value": 2}], "id": "set"}),
+    )
+    .await;
+    expect_denied(&mut ws, "set parameter speed").await;
+    assert_eq!(
+        server.parameters.get("speed").await,
+        Some(ParameterValue::Integer(1))
+    );
+
+    let mut call = vec![0x02];
+    call.extend_from_slice(&0_u32.to_le_bytes());
+    call.extend_from_slice(&3_u32.to_le_bytes());
+    call.extend_from_slice(&4_u32.to_le_bytes());
+    call.extend_from_slice(b"json{}");
+    send_binary(&mut ws, call).await;
+    expect_denied(&mut ws, "call service /reset").await;
+    let failure = next_op(&mut ws, "serviceCallFailure").await;
+    assert_eq!(failure["callId"], 3);
+    assert!(!called.load(Ordering::Relaxed));
+
+    send_json(
+        &mut ws,
+        json!({"op": "fetchAsset", "uri": "package://robot/mesh.stl", "requestId": 5}),
+    )
+    .await;
+    expect_denied(&mut ws, "fetch asset package://robot/mesh.stl").await;
+    let Message::Binary(response) = next_message(&mut ws).await else {
+        panic!("Expected a fetch asset response.");
+    };
+    assert_eq!(response[0], 0x04);
+    assert_eq!(response[1..5], 5_u32.to_le_bytes());
+    // The request failed instead of returning the asset.
+    assert_eq!(response[5], 1);
+
+    for (id, topic) in [(1_u32, "/cmd_vel"), (2, "/allowed")] {
+        send_json(
+            &mut ws,
+            json!({
+                "op": "advertise",
+                "channels": [{"id": id, "topic": topic, "encoding": "json", "schemaName": "Twist"}],
+            }),
+        )
+        .await;
+        let mut data = vec![0x01];
+        data.extend_from_slice(&id.to_le_bytes());
+        data.extend_from_slice(b"{}");
+        send_binary(&mut ws, data).await;
+    }
+    expect_denied(&mut ws, "advertise /cmd_vel").await;
+    // Requests are handled in order, so the data on the denied channel was handled before.
+    let message = tokio::time::timeout(common::TIMEOUT, allowed_messages.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(message.channel.topic, "/allowed");
+    assert!(denied_messages.next().now_or_never().is_none());
+
+    serving.abort();
+}
@@ -0,0 +1,49 @@
+//! Advertising and unadvertising channels of the server.
+
+mod common;
+
+use serde_json::json;
+
+use foxglove_ws::FoxgloveWebSocket;
+
+use common::{connect, next_op, send_json};
+
+#[tokio::test]
+async fn unadvertises_channels_to_all_clients() {
+    let server = FoxgloveWebSocket::new("robot");
+    let channel = server
+        .create_publisher("/topic", "json", "Type", "{}", Some("jsonschema"), false)
+        .await
+        .unwrap();
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    let serving = tokio::spawn(serving);
+    let mut subscriber = connect(addr, "/").await;
+    let mut watcher = connect(addr, "/").await;
+    let mut channel_id = json!(null);
+    for ws in [&mut subscriber, &mut watcher] {
+        next_op(ws, "serverInfo").await;
+        let advertise = next_op(ws, "advertise").await;
+        channel_id = advertise["channels"][0]["id"].clone();
+    }
+    send_json(
+        &mut subscriber,
+        json!({"op": "subscribe", "subscriptions": [{"id": 1, "channelId": channel_id}]}),
+    )
+    .await;
+    // The subscription is in place once the reply to a later request arrives.
+    send_json(
+        &mut subscriber,
+        json!({"op": "getParameters", "parameterNames": [], "id": "sync"}),
+    )
+    .await;
+    next_op(&mut subscriber, "parameterValues").await;
+
+    // Clients that did not subscribe were told about the channel as well and have to forget it.
+    channel.unadvertise().await.unwrap();
+    for ws in [&mut subscriber, &mut watcher] {
+        let unadvertise = next_op(ws, "unadvertise").await;
+        assert_eq!(unadvertise["channelIds"], json!([channel_id]));
+    }
+
+    serving.abort();
+}
@@ -0,0 +1,106 @@
+//! Clients publishing messages to the server.
+
+mod common;
+
+use futures_util::StreamExt;
+use serde_json::json;
+
+use foxglove_ws::{ClientMessageStream, ClientPublishedMessage, FoxgloveWebSocket};
+
+use common::{connect, next_op, send_binary, send_json, TIMEOUT};
+
+/// Builds a binary message data frame of a client.
+fn message_data(channel_id: u32, data: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x01];
+    frame.extend_from_slice(&channel_id.to_le_bytes());
+    frame.extend_from_slice(data);
+    frame
+}
+
+fn advertise(channel_id: u32) -> serde_json::Value {
+    json!({
+        "op": "advertise",
+        "channels": [{
+            "id": channel_id,
+            "topic": "/cmd_vel",
+            "encoding": "json",
+            "schemaName": "Twist",
+            "schema": "{}",
+            "schemaEncoding": "jsonschema",
+        }],
+    })
+}
+
+async fn next_client_message(messages: &mut ClientMessageStream) -> ClientPublishedMessage {
+    tokio::time::timeout(TIMEOUT, messages.next())
+        .await
+        .expect("Timed out waiting for a client message.")
+        .expect("Stream of client messages ended.")
+}
+
+#[tokio::test]
+async fn receives_messages_on_advertised_channels() {
+    let server = FoxgloveWebSocket::new("robot");
+    let mut messages = server.subscribe_client_messages("/cmd_vel").await;
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    let serving = tokio::spawn(serving);
+    let mut ws = connect(addr, "/").await;
+    let server_info = next_op(&mut ws, "serverInfo").await;
+    assert!(server_info["capabilities"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("clientPublish")));
+    let client_id = *server.stats().await.clients.keys().next().unwrap();
+
+    send_json(&mut ws, advertise(1)).await;
+    send_binary(&mut ws, message_data(1, b"{\"x\": 1}")).await;
+    let message = next_client_message(&mut messages).await;
+    assert_eq!(message.client_id, client_id);
+    assert_eq!(message.channel.topic, "/cmd_vel");
+    assert_eq!(message.channel.encoding, "json");
+    assert_eq!(message.channel.schema_name, "Twist");
+    assert_eq!(message.channel.schema.as_deref(), Some("{}"));
+    assert_eq!(
+        message.channel.schema_encoding.as_deref(),
+        Some("jsonschema")
+    );
+    assert_eq!(message.data, b"{\"x\": 1}");
+
+    // Data on the unadvertised channel and on a channel that was never advertised is dropped
+    // without disconnecting the client. Messages of a client are handled in order, so the next
+    // message to arrive is the one on the channel advertised afterwards.
+    send_json(&mut ws, json!({"op": "unadvertise", "channelIds": [1]})).await;
+    send_binary(&mut ws, message_data(1, b"{\"x\": 2}")).await;
+    send_binary(&mut ws, message_data(7, b"{\"x\": 3}")).await;
+    send_json(&mut ws, advertise(2)).await;
+    send_binary(&mut ws, message_data(2, b"{\"x\": 4}")).await;
+    let message = next_client_message(&mut messages).await;
+    assert_eq!(message.client_id, client_id);
+    assert_eq!(message.data, b"{\"x\": 4}");
+
+    serving.abort();
+}
+
+#[tokio::test]
+async fn rejects_channels_with_unsupported_encodings() {
+    let server = FoxgloveWebSocket::builder()
+        .supported_encodings(["json"])
+        .build();
+    let _messages = server.subscribe_client_messages("/cmd_vel").await;
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    let serving = tokio::spawn(serving);
+    let mut ws = connect(addr, "/").await;
+    next_op(&mut ws, "serverInfo").await;
+
+    let mut advertise = advertise(1);
+    advertise["channels"][0]["encoding"] = json!("cbor");
+    send_json(&mut ws, advertise).await;
+    let status = next_op(&mut ws, "status").await;
+    assert_eq!(status["level"], 2);
+    assert_eq!(
+        status["message"],
+        "Unsupported encoding cbor for /cmd_vel, supported are: json."
+    );
+
+    serving.abort();
+}
@@ -0,0 +1,93 @@
+//! Clients of the integration tests.
+
+#![allow(dead_code)]
+
+use std::{net::SocketAddr, time::Duration};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_tungstenite::{
+    tungstenite::{client::IntoClientRequest, Message},
+    WebSocketStream,
+};
+
+/// Time the tests wait for the server.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Completes the WebSocket handshake for `url` on `stream`, offering the Foxglove subprotocol.
+pub async fn handshake<S>(url: &str, stream: S) -> WebSocketStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut request = url.into_client_request().unwrap();
+    request.headers_mut().insert(
+        "Sec-WebSocket-Protocol",
+        "foxglove.websocket.v1".parse().unwrap(),
+    );
+    let (ws, _) = tokio_tungstenite::client_async(request, stream)
+        .await
+        .unwrap();
+    ws
+}
+
+/// Connects to a server listening on `addr`, requesting `path`.
+pub async fn connect(addr: SocketAddr, path: &str) -> WebSocketStream<TcpStream> {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    handshake(&format!("ws://{}{}", addr, path), stream).await
+}
+
+/// Returns the next message of the server.
+pub async fn next_message<S>(ws: &mut WebSocketStream<S>) -> Message
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    tokio::time::timeout(TIMEOUT, ws.next())
+        .await
+        .expect("Timed out waiting for a message.")
+        .expect("Connection closed.")
+        .unwrap()
+}
+
+/// Returns the next text message of the server as JSON.
+pub async fn next_json<S>(ws: &mut WebSocketStream<S>) -> serde_json::Value
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    loop {
+        if let Message::Text(text) = next_message(ws).await {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+/// Returns the next text message of the server with operation `op`, skipping all others.
+pub async fn next_op<S>(ws: &mut WebSocketStream<S>, op: &str) -> serde_json::Value
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    loop {
+        let message = next_json(ws).await;
+        if message["op"] == op {
+            return message;
+        }
+    }
+}
+
+/// Sends a JSON message to the server.
+pub async fn send_json<S>(ws: &mut WebSocketStream<S>, message: serde_json::Value)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    ws.send(Message::text(message.to_string())).await.unwrap();
+}
+
+/// Sends a binary message to the server.
+pub async fn send_binary<S>(ws: &mut WebSocketStream<S>, data: Vec<u8>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    ws.send(Message::binary(data)).await.unwrap();
+}
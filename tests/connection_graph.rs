@@ -0,0 +1,77 @@
+//! Clients subscribing to the connection graph.
+
+mod common;
+
+use serde_json::json;
+
+use foxglove_ws::{Capability, FoxgloveWebSocket};
+
+use common::{connect, next_op, send_json};
+
+#[tokio::test]
+async fn sends_the_graph_and_its_updates_to_subscribers() {
+    let server = FoxgloveWebSocket::builder()
+        .name("robot")
+        .enable_capability(Capability::ConnectionGraph)
+        .build();
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    let serving = tokio::spawn(serving);
+    let _channel = server
+        .create_publisher("/topic", "json", "Type", "{}", Some("jsonschema"), false)
+        .await
+        .unwrap();
+
+    let mut ws = connect(addr, "/").await;
+    let server_info = next_op(&mut ws, "serverInfo").await;
+    assert!(server_info["capabilities"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("connectionGraph")));
+
+    send_json(&mut ws, json!({"op": "subscribeConnectionGraph"})).await;
+    let update = next_op(&mut ws, "connectionGraphUpdate").await;
+    assert_eq!(
+        update,
+        json!({
+            "op": "connectionGraphUpdate",
+            "publishedTopics": [{"name": "/topic", "publisherIds": ["robot"]}],
+            "subscribedTopics": [],
+            "advertisedServices": [],
+            "removedTopics": [],
+            "removedServices": [],
+        })
+    );
+
+    server
+        .connection_graph
+        .add_subscriber("/topic", "node")
+        .await
+        .unwrap();
+    let update = next_op(&mut ws, "connectionGraphUpdate").await;
+    assert_eq!(update["publishedTopics"], json!([]));
+    assert_eq!(
+        update["subscribedTopics"],
+        json!([{"name": "/topic", "subscriberIds": ["node"]}])
+    );
+
+    server
+        .connection_graph
+        .add_service("/reset", "node")
+        .await
+        .unwrap();
+    let update = next_op(&mut ws, "connectionGraphUpdate").await;
+    assert_eq!(
+        update["advertisedServices"],
+        json!([{"name": "/reset", "providerIds": ["node"]}])
+    );
+
+    server
+        .connection_graph
+        .remove_service("/reset", "node")
+        .await
+        .unwrap();
+    let update = next_op(&mut ws, "connectionGraphUpdate").await;
+    assert_eq!(update["removedServices"], json!(["/reset"]));
+
+    serving.abort();
+}
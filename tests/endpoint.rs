@@ -0,0 +1,66 @@
+//! The endpoint mounted into other applications.
+
+mod common;
+
+use warp::Filter;
+
+use foxglove_ws::FoxgloveWebSocket;
+
+use common::{connect, next_op};
+
+#[tokio::test]
+async fn serves_clients_below_a_warp_path() {
+    let server = FoxgloveWebSocket::new("robot");
+    let status = warp::path("status").map(|| "ok");
+    let foxglove = warp::path("foxglove").and(server.filter());
+    let (addr, serving) = warp::serve(status.or(foxglove)).bind_ephemeral(([127, 0, 0, 1], 0));
+    let serving = tokio::spawn(serving);
+
+    let mut ws = connect(addr, "/foxglove").await;
+    let server_info = next_op(&mut ws, "serverInfo").await;
+    assert_eq!(server_info["name"], "robot");
+
+    let _channel = server
+        .create_publisher("/topic", "json", "Type", "{}", Some("jsonschema"), false)
+        .await
+        .unwrap();
+    loop {
+        let advertise = next_op(&mut ws, "advertise").await;
+        if advertise["channels"][0]["topic"] == "/topic" {
+            break;
+        }
+    }
+
+    serving.abort();
+}
+
+#[cfg(feature = "axum")]
+#[tokio::test]
+async fn serves_clients_below_an_axum_path() {
+    use axum::{routing::get, Router};
+
+    let server = FoxgloveWebSocket::new("robot");
+    let app = Router::new()
+        .route("/status", get(|| async { "ok" }))
+        .route_service("/foxglove", server.axum_service());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let serving = tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let mut ws = connect(addr, "/foxglove").await;
+    let server_info = next_op(&mut ws, "serverInfo").await;
+    assert_eq!(server_info["name"], "robot");
+
+    let _channel = server
+        .create_publisher("/topic", "json", "Type", "{}", Some("jsonschema"), false)
+        .await
+        .unwrap();
+    loop {
+        let advertise = next_op(&mut ws, "advertise").await;
+        if advertise["channels"][0]["topic"] == "/topic" {
+            break;
+        }
+    }
+
+    serving.abort();
+}
@@ -0,0 +1,106 @@
+//! Channels of JSON messages.
+
+#![cfg(feature = "json")]
+
+mod common;
+
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::json;
+use tokio_tungstenite::tungstenite::Message;
+
+use foxglove_ws::{FoxgloveWebSocket, Json, MessageEncoding};
+
+use common::{connect, next_message, next_op, send_json};
+
+#[derive(JsonSchema, Serialize)]
+struct Cell {
+    voltage: f64,
+}
+
+#[derive(JsonSchema, Serialize)]
+struct Battery {
+    charging: bool,
+    cells: Vec<Cell>,
+}
+
+#[tokio::test]
+async fn advertises_the_json_schema_and_sends_json() {
+    let server = FoxgloveWebSocket::new("robot");
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    let serving = tokio::spawn(serving);
+    let mut ws = connect(addr, "/").await;
+    next_op(&mut ws, "serverInfo").await;
+
+    let channel = server
+        .create_json_publisher::<Battery>("/battery", false)
+        .await
+        .unwrap();
+    let advertised = loop {
+        let advertise = next_op(&mut ws, "advertise").await;
+        if advertise["channels"][0]["topic"] == "/battery" {
+            break advertise["channels"][0].clone();
+        }
+    };
+    assert_eq!(advertised["encoding"], "json");
+    assert_eq!(advertised["schemaName"], "Battery");
+    assert_eq!(advertised["schemaEncoding"], "jsonschema");
+    let schema: serde_json::Value =
+        serde_json::from_str(advertised["schema"].as_str().unwrap()).unwrap();
+    assert_eq!(schema["type"], "object");
+    assert_eq!(schema["properties"]["charging"], json!({"type": "boolean"}));
+    // Nested types are inlined instead of referenced, Foxglove does not resolve references.
+    assert_eq!(schema["properties"]["cells"]["type"], "array");
+    assert_eq!(
+        schema["properties"]["cells"]["items"]["properties"]["voltage"]["type"],
+        "number"
+    );
+    assert!(!advertised["schema"].as_str().unwrap().contains("$ref"));
+
+    send_json(
+        &mut ws,
+        json!({"op": "subscribe", "subscriptions": [{"id": 1, "channelId": advertised["id"]}]}),
+    )
+    .await;
+    // The subscription is in place once the reply to a later request arrives.
+    send_json(
+        &mut ws,
+        json!({"op": "getParameters", "parameterNames": [], "id": "sync"}),
+    )
+    .await;
+    next_op(&mut ws, "parameterValues").await;
+
+    let battery = Battery {
+        charging: true,
+        cells: vec![Cell { voltage: 3.7 }],
+    };
+    channel.send(0, &battery).await.unwrap();
+    let Message::Binary(data) = next_message(&mut ws).await else {
+        panic!("Expected message data.");
+    };
+    let payload: serde_json::Value = serde_json::from_slice(&data[1 + 4 + 8..]).unwrap();
+    assert_eq!(
+        payload,
+        json!({"charging": true, "cells": [{"voltage": 3.7}]})
+    );
+
+    serving.abort();
+}
+
+#[test]
+fn describes_and_encodes_with_the_json_encoding() {
+    assert_eq!(Json::<Battery>::encoding(), "json");
+    assert_eq!(Json::<Battery>::schema_name(), "Battery");
+    assert_eq!(
+        Json::<Battery>::schema_encoding().as_deref(),
+        Some("jsonschema")
+    );
+    let battery = Battery {
+        charging: false,
+        cells: vec![],
+    };
+    assert_eq!(
+        Json::<Battery>::encode(&battery).unwrap(),
+        br#"{"charging":false,"cells":[]}"#
+    );
+}
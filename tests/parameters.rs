@@ -0,0 +1,216 @@
+//! Clients getting, setting and subscribing to parameters.
+
+mod common;
+
+use serde_json::json;
+
+use foxglove_ws::{Capability, FoxgloveWebSocket, ParameterValue};
+
+use common::{connect, next_op, send_json};
+
+#[tokio::test]
+async fn announces_parameter_subscriptions_by_default() {
+    let server = FoxgloveWebSocket::new("robot");
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    let serving = tokio::spawn(serving);
+    let mut ws = connect(addr, "/").await;
+    let server_info = next_op(&mut ws, "serverInfo").await;
+    assert!(server_info["capabilities"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("parametersSubscribe")));
+    next_op(&mut ws, "parameterValues").await;
+
+    send_json(
+        &mut ws,
+        json!({"op": "subscribeParameterUpdates", "parameterNames": ["speed"]}),
+    )
+    .await;
+    sync(&mut ws).await;
+    server.parameters.set("speed", 1).await.unwrap();
+    let update = next_op(&mut ws, "parameterValues").await;
+    assert_eq!(update["parameters"], json!([{"name": "speed", "value": 1}]));
+
+    serving.abort();
+}
+
+#[tokio::test]
+async fn answers_get_parameters_with_the_requested_parameters() {
+    let server = FoxgloveWebSocket::new("robot");
+    server
+        .parameters
+        .set_many([
+            ("speed".to_owned(), 1.5.into()),
+            ("name".to_owned(), "rover".into()),
+            ("enabled".to_owned(), true.into()),
+        ])
+        .await
+        .unwrap();
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    let serving = tokio::spawn(serving);
+    let mut ws = connect(addr, "/").await;
+    next_op(&mut ws, "serverInfo").await;
+    // All parameters are sent right after connecting.
+    let initial = next_op(&mut ws, "parameterValues").await;
+    assert_eq!(initial["parameters"].as_array().unwrap().len(), 3);
+    assert!(initial.get("id").is_none());
+
+    send_json(
+        &mut ws,
+        json!({"op": "getParameters", "parameterNames": ["speed", "unknown"], "id": "request"}),
+    )
+    .await;
+    let reply = next_op(&mut ws, "parameterValues").await;
+    assert_eq!(
+        reply,
+        json!({
+            "op": "parameterValues",
+            "id": "request",
+            "parameters": [{"name": "speed", "value": 1.5, "type": "float64"}],
+        })
+    );
+
+    serving.abort();
+}
+
+#[tokio::test]
+async fn echoes_set_parameters_including_deleted_ones() {
+    let server = FoxgloveWebSocket::new("robot");
+    server
+        .parameters
+        .set_many([
+            ("speed".to_owned(), 1.5.into()),
+            ("name".to_owned(), "rover".into()),
+        ])
+        .await
+        .unwrap();
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    let serving = tokio::spawn(serving);
+    let mut ws = connect(addr, "/").await;
+    next_op(&mut ws, "parameterValues").await;
+
+    send_json(
+        &mut ws,
+        json!({
+            "op": "setParameters",
+            "parameters": [{"name": "speed", "value": 2}, {"name": "name"}],
+            "id": "set",
+        }),
+    )
+    .await;
+    let reply = next_op(&mut ws, "parameterValues").await;
+    assert_eq!(
+        reply,
+        json!({
+            "op": "parameterValues",
+            "id": "set",
+            "parameters": [
+                {"name": "speed", "value": 2.0, "type": "float64"},
+                {"name": "name"},
+            ],
+        })
+    );
+    assert_eq!(
+        server.parameters.get("speed").await,
+        Some(ParameterValue::Float64(2.0))
+    );
+    assert_eq!(server.parameters.get("name").await, None);
+
+    // Without an ID, nothing is sent back.
+    send_json(
+        &mut ws,
+        json!({"op": "setParameters", "parameters": [{"name": "speed", "value": 3}]}),
+    )
+    .await;
+    send_json(
+        &mut ws,
+        json!({"op": "getParameters", "parameterNames": ["speed"], "id": "get"}),
+    )
+    .await;
+    let reply = next_op(&mut ws, "parameterValues").await;
+    assert_eq!(reply["id"], "get");
+    assert_eq!(reply["parameters"][0]["value"], 3.0);
+
+    serving.abort();
+}
+
+/// Sends `getParameters` for nothing and waits for the reply. Requests of a client are handled
+/// in order, so all earlier requests are handled once it arrives, and messages sent to the
+/// client before would have arrived before the reply.
+async fn sync<S>(ws: &mut tokio_tungstenite::WebSocketStream<S>)
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    send_json(
+        ws,
+        json!({"op": "getParameters", "parameterNames": ["none"], "id": "sync"}),
+    )
+    .await;
+    let reply = next_op(ws, "parameterValues").await;
+    assert_eq!(reply["id"], "sync", "Got unexpected parameter values.");
+}
+
+#[tokio::test]
+async fn pushes_changed_parameters_to_subscribed_clients_only() {
+    let server = FoxgloveWebSocket::builder()
+        .enable_capability(Capability::ParametersSubscribe)
+        .build();
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    let serving = tokio::spawn(serving);
+    let mut speed_client = connect(addr, "/").await;
+    let mut name_client = connect(addr, "/").await;
+    let mut other_client = connect(addr, "/").await;
+    for ws in [&mut speed_client, &mut name_client, &mut other_client] {
+        next_op(ws, "parameterValues").await;
+    }
+    send_json(
+        &mut speed_client,
+        json!({"op": "subscribeParameterUpdates", "parameterNames": ["speed"]}),
+    )
+    .await;
+    send_json(
+        &mut name_client,
+        json!({"op": "subscribeParameterUpdates", "parameterNames": ["name"]}),
+    )
+    .await;
+    for ws in [&mut speed_client, &mut name_client, &mut other_client] {
+        sync(ws).await;
+    }
+
+    server.parameters.set("speed", 2).await.unwrap();
+    let update = next_op(&mut speed_client, "parameterValues").await;
+    assert_eq!(
+        update,
+        json!({
+            "op": "parameterValues",
+            "parameters": [{"name": "speed", "value": 2}],
+        })
+    );
+    sync(&mut name_client).await;
+    sync(&mut other_client).await;
+
+    // Values set by a client are pushed as well.
+    send_json(
+        &mut other_client,
+        json!({"op": "setParameters", "parameters": [{"name": "name", "value": "rover"}]}),
+    )
+    .await;
+    let update = next_op(&mut name_client, "parameterValues").await;
+    assert_eq!(
+        update["parameters"],
+        json!([{"name": "name", "value": "rover"}])
+    );
+    sync(&mut speed_client).await;
+
+    // Nothing is pushed after unsubscribing.
+    send_json(
+        &mut speed_client,
+        json!({"op": "unsubscribeParameterUpdates", "parameterNames": ["speed"]}),
+    )
+    .await;
+    sync(&mut speed_client).await;
+    server.parameters.set("speed", 3).await.unwrap();
+    sync(&mut speed_client).await;
+
+    serving.abort();
+}
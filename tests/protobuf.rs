@@ -0,0 +1,78 @@
+//! Channels of protobuf messages.
+
+#![cfg(feature = "protobuf")]
+
+mod common;
+
+use base64::{engine::general_purpose, Engine as _};
+use prost::Message as _;
+use prost_reflect::prost_types::{Api, FileDescriptorSet, Timestamp};
+
+use foxglove_ws::{FoxgloveWebSocket, MessageEncoding, Protobuf};
+
+use common::{connect, next_op};
+
+#[tokio::test]
+async fn advertises_the_descriptors_of_all_files_a_message_depends_on() {
+    let server = FoxgloveWebSocket::new("robot");
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    let serving = tokio::spawn(serving);
+    let mut ws = connect(addr, "/").await;
+    next_op(&mut ws, "serverInfo").await;
+
+    let _channel = server
+        .create_protobuf_publisher::<Api>("/api", false)
+        .await
+        .unwrap();
+    let channel = loop {
+        let advertise = next_op(&mut ws, "advertise").await;
+        if advertise["channels"][0]["topic"] == "/api" {
+            break advertise["channels"][0].clone();
+        }
+    };
+    assert_eq!(channel["encoding"], "protobuf");
+    assert_eq!(channel["schemaName"], "google.protobuf.Api");
+    assert_eq!(channel["schemaEncoding"], "protobuf");
+
+    let schema = general_purpose::STANDARD_NO_PAD
+        .decode(channel["schema"].as_str().unwrap())
+        .unwrap();
+    let set = FileDescriptorSet::decode(schema.as_slice()).unwrap();
+    let files: Vec<_> = set.file.iter().map(|file| file.name()).collect();
+    // api.proto imports source_context.proto and type.proto, which imports any.proto. Each file
+    // comes once and after the files it depends on.
+    assert_eq!(files.len(), 4);
+    for file in [
+        "google/protobuf/any.proto",
+        "google/protobuf/source_context.proto",
+        "google/protobuf/type.proto",
+    ] {
+        assert!(files.contains(&file), "{} is missing.", file);
+    }
+    assert_eq!(files.last(), Some(&"google/protobuf/api.proto"));
+    let position = |name| files.iter().position(|file| *file == name).unwrap();
+    assert!(position("google/protobuf/any.proto") < position("google/protobuf/type.proto"));
+
+    serving.abort();
+}
+
+#[test]
+fn describes_and_encodes_with_the_protobuf_encoding() {
+    assert_eq!(Protobuf::<Timestamp>::encoding(), "protobuf");
+    assert_eq!(
+        Protobuf::<Timestamp>::schema_name(),
+        "google.protobuf.Timestamp"
+    );
+    assert_eq!(
+        Protobuf::<Timestamp>::schema_encoding().as_deref(),
+        Some("protobuf")
+    );
+    let stamp = Timestamp {
+        seconds: 1,
+        nanos: 2,
+    };
+    assert_eq!(
+        Protobuf::<Timestamp>::encode(&stamp).unwrap(),
+        stamp.encode_to_vec()
+    );
+}
@@ -0,0 +1,85 @@
+//! Binding the server to addresses and shutting it down.
+
+mod common;
+
+use tokio::sync::oneshot;
+use tokio_tungstenite::tungstenite::Message;
+
+use foxglove_ws::FoxgloveWebSocket;
+
+use common::{connect, next_message, next_op, TIMEOUT};
+
+#[tokio::test]
+async fn reports_the_bound_port() {
+    let server = FoxgloveWebSocket::new("robot");
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    assert_ne!(addr.port(), 0);
+    let serving = tokio::spawn(serving);
+
+    let mut ws = connect(addr, "/").await;
+    next_op(&mut ws, "serverInfo").await;
+
+    serving.abort();
+}
+
+#[tokio::test]
+async fn fails_to_bind_a_taken_port() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let server = FoxgloveWebSocket::new("robot");
+    assert!(server.try_bind(listener.local_addr().unwrap()).is_err());
+}
+
+#[tokio::test]
+async fn closes_connections_on_shutdown() {
+    let server = FoxgloveWebSocket::new("robot");
+    let (shutdown, shutdown_signal) = oneshot::channel::<()>();
+    let (addr, serving) = server
+        .serve_with_shutdown(([127, 0, 0, 1], 0), async {
+            shutdown_signal.await.ok();
+        })
+        .unwrap();
+    let serving = tokio::spawn(serving);
+    let mut ws = connect(addr, "/").await;
+    next_op(&mut ws, "serverInfo").await;
+
+    shutdown.send(()).unwrap();
+    let status = next_op(&mut ws, "status").await;
+    assert_eq!(status["message"], "Server is shutting down.");
+    assert!(matches!(next_message(&mut ws).await, Message::Close(_)));
+    tokio::time::timeout(TIMEOUT, serving)
+        .await
+        .expect("Timed out waiting for the server to finish.")
+        .unwrap();
+
+    // The server does not accept connections anymore.
+    assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+}
+
+#[tokio::test]
+async fn serves_again_after_shutdown() {
+    let server = FoxgloveWebSocket::new("robot");
+    let (addr, serving) = server
+        .serve_with_shutdown(([127, 0, 0, 1], 0), async {})
+        .unwrap();
+    tokio::time::timeout(TIMEOUT, serving)
+        .await
+        .expect("Timed out waiting for the server to finish.");
+
+    let (addr, serving) = server.try_bind(addr).unwrap();
+    let serving = tokio::spawn(serving);
+    let mut ws = connect(addr, "/").await;
+    next_op(&mut ws, "serverInfo").await;
+    // The server has no channels yet.
+    let advertise = next_op(&mut ws, "advertise").await;
+    assert_eq!(advertise["channels"], serde_json::json!([]));
+
+    // The client is not disconnected as if the server was still shutting down.
+    let _channel = server
+        .create_publisher("/topic", "json", "Type", "{}", Some("jsonschema"), false)
+        .await
+        .unwrap();
+    let advertise = next_op(&mut ws, "advertise").await;
+    assert_eq!(advertise["channels"][0]["topic"], "/topic");
+
+    serving.abort();
+}
@@ -0,0 +1,107 @@
+//! Clients calling services of the server.
+
+mod common;
+
+use anyhow::anyhow;
+use serde_json::json;
+use tokio_tungstenite::tungstenite::Message;
+
+use foxglove_ws::{FoxgloveWebSocket, ServiceSchema};
+
+use common::{connect, next_message, next_op, send_binary};
+
+fn schema() -> ServiceSchema {
+    ServiceSchema::new("json", "Echo", "{}", "jsonschema")
+}
+
+/// Builds a binary service call request of a client.
+fn service_call_request(service_id: u32, call_id: u32, encoding: &str, data: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x02];
+    frame.extend_from_slice(&service_id.to_le_bytes());
+    frame.extend_from_slice(&call_id.to_le_bytes());
+    frame.extend_from_slice(&(encoding.len() as u32).to_le_bytes());
+    frame.extend_from_slice(encoding.as_bytes());
+    frame.extend_from_slice(data);
+    frame
+}
+
+#[tokio::test]
+async fn answers_service_calls() {
+    let server = FoxgloveWebSocket::new("robot");
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    let serving = tokio::spawn(serving);
+    let mut ws = connect(addr, "/").await;
+    next_op(&mut ws, "serverInfo").await;
+
+    let echo = server
+        .advertise_service("/echo", "Echo", schema(), schema(), |request| async move {
+            Ok(request.data)
+        })
+        .await
+        .unwrap();
+    let advertise = next_op(&mut ws, "advertiseServices").await;
+    assert_eq!(
+        advertise["services"],
+        json!([{
+            "id": 0,
+            "name": "/echo",
+            "type": "Echo",
+            "request": {
+                "encoding": "json",
+                "schemaName": "Echo",
+                "schemaEncoding": "jsonschema",
+                "schema": "{}",
+            },
+            "response": {
+                "encoding": "json",
+                "schemaName": "Echo",
+                "schemaEncoding": "jsonschema",
+                "schema": "{}",
+            },
+        }])
+    );
+    let failing = server
+        .advertise_service("/fail", "Echo", schema(), schema(), |_| async {
+            Err(anyhow!("Out of order."))
+        })
+        .await
+        .unwrap();
+    next_op(&mut ws, "advertiseServices").await;
+
+    send_binary(&mut ws, service_call_request(0, 7, "json", b"{\"a\": 1}")).await;
+    let Message::Binary(response) = next_message(&mut ws).await else {
+        panic!("Expected a service call response.");
+    };
+    let mut expected = vec![0x03];
+    expected.extend_from_slice(&0_u32.to_le_bytes());
+    expected.extend_from_slice(&7_u32.to_le_bytes());
+    expected.extend_from_slice(&4_u32.to_le_bytes());
+    expected.extend_from_slice(b"json{\"a\": 1}");
+    assert_eq!(response, expected);
+
+    send_binary(&mut ws, service_call_request(1, 8, "json", b"{}")).await;
+    let failure = next_op(&mut ws, "serviceCallFailure").await;
+    assert_eq!(
+        failure,
+        json!({
+            "op": "serviceCallFailure",
+            "serviceId": 1,
+            "callId": 8,
+            "message": "Out of order.",
+        })
+    );
+
+    drop(echo);
+    let unadvertise = next_op(&mut ws, "unadvertiseServices").await;
+    assert_eq!(unadvertise["serviceIds"], json!([0]));
+    failing.unadvertise().await.unwrap();
+    let unadvertise = next_op(&mut ws, "unadvertiseServices").await;
+    assert_eq!(unadvertise["serviceIds"], json!([1]));
+
+    // Calls of services that are gone fail.
+    send_binary(&mut ws, service_call_request(0, 9, "json", b"{}")).await;
+    let failure = next_op(&mut ws, "serviceCallFailure").await;
+    assert_eq!(failure["callId"], 9);
+
+    serving.abort();
+}
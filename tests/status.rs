@@ -0,0 +1,76 @@
+//! Status messages shown to clients.
+
+mod common;
+
+use serde_json::json;
+
+use foxglove_ws::{FoxgloveWebSocket, StatusLevel};
+
+use common::{connect, next_op};
+
+#[tokio::test]
+async fn shows_statuses_until_their_handle_is_dropped() {
+    let server = FoxgloveWebSocket::new("robot");
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    let serving = tokio::spawn(serving);
+    let mut ws = connect(addr, "/").await;
+    next_op(&mut ws, "serverInfo").await;
+
+    let status = server
+        .create_status(StatusLevel::Warning, "Lidar disconnected")
+        .await
+        .unwrap();
+    let message = next_op(&mut ws, "status").await;
+    assert_eq!(
+        message,
+        json!({"op": "status", "level": 1, "message": "Lidar disconnected", "id": status.id()})
+    );
+
+    // Clients connecting while the handle is alive see the status as well.
+    let mut late = connect(addr, "/").await;
+    let message = next_op(&mut late, "status").await;
+    assert_eq!(message["id"], status.id());
+
+    let id = status.id().to_owned();
+    drop(status);
+    for ws in [&mut ws, &mut late] {
+        let message = next_op(ws, "removeStatus").await;
+        assert_eq!(message, json!({"op": "removeStatus", "statusIds": [id]}));
+    }
+
+    serving.abort();
+}
+
+#[tokio::test]
+async fn publishes_and_removes_statuses_by_id() {
+    let server = FoxgloveWebSocket::new("robot");
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    let serving = tokio::spawn(serving);
+    let mut ws = connect(addr, "/").await;
+    next_op(&mut ws, "serverInfo").await;
+
+    server
+        .publish_status(StatusLevel::Error, "Motor overheating", Some("motor"))
+        .await
+        .unwrap();
+    let message = next_op(&mut ws, "status").await;
+    assert_eq!(
+        message,
+        json!({"op": "status", "level": 2, "message": "Motor overheating", "id": "motor"})
+    );
+    server
+        .publish_status(StatusLevel::Info, "Ready", None)
+        .await
+        .unwrap();
+    let message = next_op(&mut ws, "status").await;
+    assert_eq!(
+        message,
+        json!({"op": "status", "level": 0, "message": "Ready"})
+    );
+
+    server.remove_status(&["motor"]).await.unwrap();
+    let message = next_op(&mut ws, "removeStatus").await;
+    assert_eq!(message["statusIds"], json!(["motor"]));
+
+    serving.abort();
+}
@@ -0,0 +1,32 @@
+//! Clients served on streams set up by the application.
+
+mod common;
+
+use foxglove_ws::FoxgloveWebSocket;
+
+use common::{handshake, next_op};
+
+#[tokio::test]
+async fn serves_a_client_on_an_in_memory_stream() {
+    let server = FoxgloveWebSocket::new("robot");
+    let _channel = server
+        .create_publisher("/topic", "json", "Type", "{}", Some("jsonschema"), false)
+        .await
+        .unwrap();
+
+    let (client_end, server_end) = tokio::io::duplex(64 * 1024);
+    let serving = tokio::spawn({
+        let server = server.clone();
+        async move { server.serve_stream(server_end, None).await }
+    });
+    let mut ws = handshake("ws://localhost/", client_end).await;
+
+    let server_info = next_op(&mut ws, "serverInfo").await;
+    assert_eq!(server_info["name"], "robot");
+    let advertise = next_op(&mut ws, "advertise").await;
+    assert_eq!(advertise["channels"][0]["topic"], "/topic");
+
+    // Serving the stream finishes once the connection is upgraded.
+    drop(ws);
+    serving.await.unwrap().unwrap();
+}
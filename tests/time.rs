@@ -0,0 +1,104 @@
+//! Server time broadcast to clients.
+
+mod common;
+
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio_tungstenite::tungstenite::Message;
+
+use foxglove_ws::{Capability, FoxgloveWebSocket};
+
+use common::{connect, next_message, next_op};
+
+fn time_frame(timestamp_ns: u64) -> Vec<u8> {
+    let mut frame = vec![0x02];
+    frame.extend_from_slice(&timestamp_ns.to_le_bytes());
+    frame
+}
+
+fn announces_time(server_info: &Value) -> bool {
+    server_info["capabilities"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("time"))
+}
+
+/// Returns the next binary message of the server.
+async fn next_binary<S>(ws: &mut tokio_tungstenite::WebSocketStream<S>) -> Vec<u8>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    loop {
+        if let Message::Binary(data) = next_message(ws).await {
+            return data;
+        }
+    }
+}
+
+#[tokio::test]
+async fn broadcasts_time_frames_once_time_is_used() {
+    let server = FoxgloveWebSocket::new("robot");
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    let serving = tokio::spawn(serving);
+    let mut ws = connect(addr, "/").await;
+    assert!(!announces_time(&next_op(&mut ws, "serverInfo").await));
+
+    server.broadcast_time(0x0102_0304_0506_0708).await.unwrap();
+    // The op code is followed by the timestamp as little-endian u64.
+    assert_eq!(
+        next_binary(&mut ws).await,
+        [0x02, 0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01]
+    );
+
+    // Clients connecting afterwards are told about the capability and the current time.
+    let mut late = connect(addr, "/").await;
+    assert!(announces_time(&next_op(&mut late, "serverInfo").await));
+    assert_eq!(
+        next_binary(&mut late).await,
+        time_frame(0x0102_0304_0506_0708)
+    );
+
+    serving.abort();
+}
+
+#[tokio::test]
+async fn broadcasts_the_time_of_a_clock() {
+    let server = FoxgloveWebSocket::new("robot");
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    let serving = tokio::spawn(serving);
+    let mut ws = connect(addr, "/").await;
+    next_op(&mut ws, "serverInfo").await;
+
+    let clock = server
+        .start_clock(|| 42, Duration::from_millis(10))
+        .unwrap();
+    assert_eq!(next_binary(&mut ws).await, time_frame(42));
+    assert_eq!(next_binary(&mut ws).await, time_frame(42));
+    drop(clock);
+
+    serving.abort();
+}
+
+#[tokio::test]
+async fn refuses_to_start_a_clock_without_a_period() {
+    let server = FoxgloveWebSocket::new("robot");
+    let Err(err) = server.start_clock(|| 42, Duration::ZERO) else {
+        panic!("Started a clock with a zero period.");
+    };
+    assert_eq!(err.to_string(), "Period of the clock must not be zero.");
+}
+
+#[tokio::test]
+async fn does_not_announce_disabled_time() {
+    let server = FoxgloveWebSocket::builder()
+        .disable_capability(Capability::Time)
+        .build();
+    assert!(server.broadcast_time(1).await.is_err());
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    let serving = tokio::spawn(serving);
+    let mut ws = connect(addr, "/").await;
+    assert!(!announces_time(&next_op(&mut ws, "serverInfo").await));
+
+    serving.abort();
+}
@@ -0,0 +1,159 @@
+//! Clients connecting over `wss://` to a server with a self-signed certificate.
+
+#![cfg(feature = "tls")]
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use futures_util::StreamExt;
+use rcgen::CertifiedKey;
+use tokio::{net::TcpStream, sync::oneshot};
+use tokio_rustls::{
+    client::TlsStream,
+    rustls::{crypto::ring, pki_types::ServerName, ClientConfig, RootCertStore},
+    TlsConnector,
+};
+use tokio_tungstenite::{
+    tungstenite::{client::IntoClientRequest, Message},
+    WebSocketStream,
+};
+
+use foxglove_ws::{FoxgloveWebSocket, TlsConfig};
+
+fn self_signed_certificate() -> CertifiedKey {
+    rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap()
+}
+
+/// Opens a TLS connection that only trusts `certificate`.
+async fn connect_tls(
+    addr: SocketAddr,
+    certificate: &CertifiedKey,
+) -> std::io::Result<TlsStream<TcpStream>> {
+    let mut roots = RootCertStore::empty();
+    roots.add(certificate.cert.der().clone()).unwrap();
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let stream = TcpStream::connect(addr).await?;
+    TlsConnector::from(Arc::new(config))
+        .connect(ServerName::try_from("localhost").unwrap(), stream)
+        .await
+}
+
+/// Completes the WebSocket handshake on a TLS connection.
+async fn connect_websocket(
+    addr: SocketAddr,
+    stream: TlsStream<TcpStream>,
+) -> WebSocketStream<TlsStream<TcpStream>> {
+    let mut request = format!("wss://localhost:{}", addr.port())
+        .into_client_request()
+        .unwrap();
+    request.headers_mut().insert(
+        "Sec-WebSocket-Protocol",
+        "foxglove.websocket.v1".parse().unwrap(),
+    );
+    let (ws, _) = tokio_tungstenite::client_async(request, stream)
+        .await
+        .unwrap();
+    ws
+}
+
+/// Returns the next text message of the server as JSON.
+async fn next_json(ws: &mut WebSocketStream<TlsStream<TcpStream>>) -> serde_json::Value {
+    loop {
+        let message = tokio::time::timeout(Duration::from_secs(5), ws.next())
+            .await
+            .expect("Timed out waiting for a message.")
+            .expect("Connection closed.")
+            .unwrap();
+        if let Message::Text(text) = message {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+/// Returns the certificate the server presented on `stream`.
+fn peer_certificate(stream: &TlsStream<TcpStream>) -> Vec<u8> {
+    stream.get_ref().1.peer_certificates().unwrap()[0].to_vec()
+}
+
+#[tokio::test]
+async fn serves_clients_over_tls_and_reloads_certificates() {
+    let first = self_signed_certificate();
+    let tls = TlsConfig::from_pem(
+        first.cert.pem().as_bytes(),
+        first.key_pair.serialize_pem().as_bytes(),
+    )
+    .unwrap();
+    let server = FoxgloveWebSocket::new("robot");
+    let (shutdown, shutdown_signal) = oneshot::channel::<()>();
+    let (addr, serving) = server
+        .serve_tls_with_shutdown(([127, 0, 0, 1], 0), tls.clone(), async {
+            shutdown_signal.await.ok();
+        })
+        .unwrap();
+    let serving = tokio::spawn(serving);
+
+    let stream = connect_tls(addr, &first).await.unwrap();
+    assert_eq!(peer_certificate(&stream), first.cert.der().to_vec());
+    let mut established = connect_websocket(addr, stream).await;
+    let server_info = next_json(&mut established).await;
+    assert_eq!(server_info["op"], "serverInfo");
+    assert_eq!(server_info["name"], "robot");
+
+    let second = self_signed_certificate();
+    tls.reload_from_pem(
+        second.cert.pem().as_bytes(),
+        second.key_pair.serialize_pem().as_bytes(),
+    )
+    .unwrap();
+
+    // New connections get the new certificate, so clients only trusting the old one fail.
+    assert!(connect_tls(addr, &first).await.is_err());
+    let stream = connect_tls(addr, &second).await.unwrap();
+    assert_eq!(peer_certificate(&stream), second.cert.der().to_vec());
+    let mut reconnected = connect_websocket(addr, stream).await;
+    assert_eq!(next_json(&mut reconnected).await["op"], "serverInfo");
+
+    // The connection established with the old certificate is kept.
+    let _channel = server
+        .create_publisher("/topic", "json", "Type", "{}", Some("jsonschema"), false)
+        .await
+        .unwrap();
+    for ws in [&mut established, &mut reconnected] {
+        // Skips the messages sent right after connecting, e.g. the advertisement of no channels.
+        loop {
+            let message = next_json(ws).await;
+            if message["op"] == "advertise" && message["channels"][0]["topic"] == "/topic" {
+                break;
+            }
+        }
+    }
+
+    shutdown.send(()).unwrap();
+    serving.await.unwrap();
+}
+
+#[tokio::test]
+async fn keeps_certificate_when_reloading_invalid_pem() {
+    let certificate = self_signed_certificate();
+    let tls = TlsConfig::from_pem(
+        certificate.cert.pem().as_bytes(),
+        certificate.key_pair.serialize_pem().as_bytes(),
+    )
+    .unwrap();
+    let server = FoxgloveWebSocket::new("robot");
+    let (addr, serving) = server
+        .try_bind_tls(([127, 0, 0, 1], 0), tls.clone())
+        .unwrap();
+    let serving = tokio::spawn(serving);
+
+    assert!(tls.reload_from_pem(b"invalid", b"invalid").is_err());
+    let stream = connect_tls(addr, &certificate).await.unwrap();
+    assert_eq!(peer_certificate(&stream), certificate.cert.der().to_vec());
+    let mut ws = connect_websocket(addr, stream).await;
+    assert_eq!(next_json(&mut ws).await["op"], "serverInfo");
+
+    serving.abort();
+}
@@ -0,0 +1,94 @@
+//! Channels of typed messages.
+
+mod common;
+
+use serde_json::json;
+use tokio_tungstenite::tungstenite::Message;
+
+use foxglove_ws::{FoxgloveWebSocket, MessageEncoding, SchemaDescriptor};
+
+use common::{connect, next_message, next_op, send_json};
+
+/// A ROS 1 `std_msgs/UInt16`.
+struct UInt16(u16);
+
+impl MessageEncoding for UInt16 {
+    type Message = Self;
+
+    fn encoding() -> String {
+        "ros1".to_owned()
+    }
+
+    fn schema_name() -> String {
+        "std_msgs/UInt16".to_owned()
+    }
+
+    fn schema() -> anyhow::Result<SchemaDescriptor> {
+        Ok("uint16 data".into())
+    }
+
+    fn schema_encoding() -> Option<String> {
+        Some("ros1msg".to_owned())
+    }
+
+    fn encode(message: &Self) -> anyhow::Result<Vec<u8>> {
+        Ok(message.0.to_le_bytes().to_vec())
+    }
+}
+
+#[tokio::test]
+async fn advertises_and_sends_what_the_encoding_describes() {
+    let server = FoxgloveWebSocket::new("robot");
+    let (addr, serving) = server.try_bind(([127, 0, 0, 1], 0)).unwrap();
+    let serving = tokio::spawn(serving);
+    let mut ws = connect(addr, "/").await;
+    next_op(&mut ws, "serverInfo").await;
+
+    let channel = server
+        .create_typed_publisher::<UInt16>("/count", false)
+        .await
+        .unwrap();
+    let advertise = loop {
+        let advertise = next_op(&mut ws, "advertise").await;
+        if !advertise["channels"].as_array().unwrap().is_empty() {
+            break advertise;
+        }
+    };
+    let channel_id = advertise["channels"][0]["id"].clone();
+    assert_eq!(
+        advertise["channels"][0],
+        json!({
+            "id": channel_id,
+            "topic": "/count",
+            "encoding": UInt16::encoding(),
+            "schemaName": UInt16::schema_name(),
+            "schema": "uint16 data",
+            "schemaEncoding": UInt16::schema_encoding(),
+        })
+    );
+
+    send_json(
+        &mut ws,
+        json!({"op": "subscribe", "subscriptions": [{"id": 3, "channelId": channel_id}]}),
+    )
+    .await;
+    // The subscription is in place once the reply to a later request arrives.
+    send_json(
+        &mut ws,
+        json!({"op": "getParameters", "parameterNames": [], "id": "sync"}),
+    )
+    .await;
+    next_op(&mut ws, "parameterValues").await;
+
+    channel.send(17, &UInt16(0x0102)).await.unwrap();
+    let Message::Binary(data) = next_message(&mut ws).await else {
+        panic!("Expected message data.");
+    };
+    let mut expected = vec![0x01];
+    expected.extend_from_slice(&3_u32.to_le_bytes());
+    expected.extend_from_slice(&17_u64.to_le_bytes());
+    expected.extend_from_slice(&UInt16::encode(&UInt16(0x0102)).unwrap());
+    assert_eq!(data, expected);
+
+    serving.abort();
+}